@@ -0,0 +1,52 @@
+//! Measures overhead of calling mockable functions
+//!
+//! Run with `cargo bench`. Calls of mockable functions skip looking for mocks, when there are
+//! no mocks in any thread, otherwise they pay for the lookup even if they aren't mocked themselves.
+
+use mocktopus::macros::*;
+use mocktopus::mocking::*;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 10_000_000;
+
+#[inline(never)]
+fn regular(a: u64, b: u64) -> u64 {
+    a.wrapping_mul(b)
+}
+
+#[mockable]
+#[inline(never)]
+fn mockable(a: u64, b: u64) -> u64 {
+    a.wrapping_mul(b)
+}
+
+#[mockable]
+fn other() {}
+
+fn bench(name: &str, mut call: impl FnMut(u64, u64) -> u64) {
+    for i in 0..ITERATIONS / 10 {
+        black_box(call(black_box(i as u64), 3));
+    }
+    let start = Instant::now();
+    for i in 0..ITERATIONS {
+        black_box(call(black_box(i as u64), 3));
+    }
+    report(name, start.elapsed());
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let nanos_per_call = elapsed.as_nanos() as f64 / ITERATIONS as f64;
+    println!("{:<50} {:>8.2} ns/call", name, nanos_per_call);
+}
+
+fn main() {
+    bench("regular function", regular);
+    bench("mockable function, no mocks", mockable);
+    other.mock_safe(|| MockResult::Continue(()));
+    bench("mockable function, other function mocked", mockable);
+    mockable.mock_safe(|a: u64, b: u64| MockResult::Continue((a, b)));
+    bench("mockable function, mocked to continue", mockable);
+    mockable.mock_safe(|a: u64, b: u64| MockResult::Return(a.wrapping_add(b)));
+    bench("mockable function, mocked to return", mockable);
+}
@@ -0,0 +1,139 @@
+use crate::type_tools;
+use quote::{format_ident, quote, ToTokens};
+use syn::{
+    parse_quote, Attribute, FnArg, GenericParam, Ident, Item, Lifetime, LifetimeDef, Pat, PatIdent,
+    PatType, Receiver, Signature, Type, Visibility,
+};
+
+/// Builds `{fn}_args` struct with fields named after arguments of a mockable function
+/// and its `NamedArgs` impl. Both get `cfg_attrs`, so they exist only together with the function.
+///
+/// Generic and async functions and ones taking `impl Trait` argument are skipped.
+pub fn build_for_fn(
+    cfg_attrs: &[Attribute],
+    vis: &Visibility,
+    sig: &Signature,
+) -> Option<Vec<Item>> {
+    let args_ident = format_ident!("{}_args", sig.ident);
+    build(&args_ident, cfg_attrs, vis, sig, None)
+}
+
+/// Builds `{Type}_{method}_args` struct with fields named after arguments of a mockable method
+/// and its `NamedArgs` impl. The receiver is stored in `self_` field.
+///
+/// Only methods of non-generic impls of plain types are supported.
+pub fn build_for_method(
+    type_ident: &Ident,
+    self_ty: &Type,
+    cfg_attrs: &[Attribute],
+    vis: &Visibility,
+    sig: &Signature,
+) -> Option<Vec<Item>> {
+    let args_ident = format_ident!("{}_{}_args", type_ident, sig.ident);
+    build(&args_ident, cfg_attrs, vis, sig, Some(self_ty))
+}
+
+fn build(
+    args_ident: &Ident,
+    cfg_attrs: &[Attribute],
+    vis: &Visibility,
+    sig: &Signature,
+    self_ty: Option<&Type>,
+) -> Option<Vec<Item>> {
+    let is_generic = sig
+        .generics
+        .params
+        .iter()
+        .any(|param| !matches!(param, GenericParam::Lifetime(_)));
+    if sig.asyncness.is_some() || is_generic {
+        return None;
+    }
+    let mut lifetimes: Vec<Lifetime> = Vec::new();
+    let mut field_idents = Vec::new();
+    let mut field_types = Vec::new();
+    for (i, fn_arg) in sig.inputs.iter().enumerate() {
+        let (field_ident, field_type) = match (fn_arg, self_ty) {
+            (
+                FnArg::Receiver(Receiver {
+                    reference: Some((_, ref lifetime)),
+                    ref mutability,
+                    ..
+                }),
+                Some(self_ty),
+            ) => (
+                format_ident!("self_"),
+                quote!(& #lifetime #mutability #self_ty),
+            ),
+            (FnArg::Receiver(_), Some(self_ty)) => {
+                (format_ident!("self_"), self_ty.to_token_stream())
+            }
+            (FnArg::Receiver(_), None) => return None,
+            (FnArg::Typed(PatType { ty, .. }), _)
+                if type_tools::contains_ident(ty.to_token_stream(), "impl") =>
+            {
+                return None
+            }
+            (FnArg::Typed(PatType { pat, ty, .. }), _) => {
+                let field_ident = match **pat {
+                    Pat::Ident(PatIdent { ref ident, .. }) if ident == "self" => {
+                        format_ident!("self_")
+                    }
+                    Pat::Ident(PatIdent { ref ident, .. }) => ident.clone(),
+                    _ => format_ident!("arg{}", i),
+                };
+                let field_type = match self_ty {
+                    Some(self_ty) => type_tools::replace_self_type(ty, self_ty).into_token_stream(),
+                    None => ty.to_token_stream(),
+                };
+                (field_ident, field_type)
+            }
+        };
+        field_idents.push(field_ident);
+        field_types.push(type_tools::name_elided_lifetimes(
+            field_type,
+            &mut lifetimes,
+        ));
+    }
+    let mut lifetime_defs: Vec<LifetimeDef> = sig
+        .generics
+        .params
+        .iter()
+        .filter_map(|param| match param {
+            GenericParam::Lifetime(lifetime_def) => Some(lifetime_def.clone()),
+            _ => None,
+        })
+        .collect();
+    lifetime_defs.extend(lifetimes.into_iter().map(LifetimeDef::new));
+    let lifetime_params: Vec<_> = lifetime_defs
+        .iter()
+        .map(|lifetime_def| &lifetime_def.lifetime)
+        .collect();
+    let where_clause = &sig.generics.where_clause;
+    let fields = quote!(#(#field_idents),*);
+    let fields_tuple = quote!((#(#field_idents,)*));
+    let tuple = quote!((#(#field_types,)*));
+    Some(vec![
+        parse_quote! {
+            #(#cfg_attrs)*
+            #[allow(non_camel_case_types, dead_code)]
+            #vis struct #args_ident<#(#lifetime_defs),*> #where_clause {
+                #(#vis #field_idents: #field_types,)*
+            }
+        },
+        parse_quote! {
+            #(#cfg_attrs)*
+            #[allow(clippy::unused_unit)]
+            impl<#(#lifetime_defs),*> ::mocktopus::mocking::NamedArgs<#tuple>
+                for #args_ident<#(#lifetime_params),*> #where_clause
+            {
+                fn from_tuple(#fields_tuple: #tuple) -> Self {
+                    #args_ident { #fields }
+                }
+
+                fn into_tuple(self) -> #tuple {
+                    (#(self.#field_idents,)*)
+                }
+            }
+        },
+    ])
+}
@@ -0,0 +1,49 @@
+use proc_macro2::{Span, TokenStream};
+use quote::{quote, quote_spanned};
+
+/// Errors and warnings collected while making code mockable
+///
+/// Errors are emitted as `compile_error!` invocations. Stable Rust has no API for warnings,
+/// so they are emitted as uses of deprecated items, which rustc reports as warnings.
+#[derive(Default)]
+pub struct Diagnostics {
+    errors: Vec<syn::Error>,
+    warnings: Vec<(Span, String)>,
+}
+
+impl Diagnostics {
+    pub fn error(&mut self, span: Span, message: impl Into<String>) {
+        self.errors.push(syn::Error::new(span, message.into()))
+    }
+
+    pub fn warning(&mut self, span: Span, message: impl Into<String>) {
+        self.warnings.push((span, message.into()))
+    }
+
+    /// Warns about a construct, which is silently left not mockable unless `warn-skipped` feature is enabled
+    pub fn skipped(&mut self, span: Span, message: impl Into<String>) {
+        if cfg!(feature = "warn-skipped") {
+            self.warning(span, message)
+        }
+    }
+
+    pub fn into_token_stream(self) -> TokenStream {
+        let errors = self
+            .errors
+            .into_iter()
+            .map(|error| error.to_compile_error());
+        let warnings = self.warnings.into_iter().map(|(span, message)| {
+            let message = format!("mocktopus: {}", message);
+            let warning_use = quote_spanned!(span=> mocktopus_warning);
+            quote! {
+                const _: () = {
+                    #[deprecated(note = #message)]
+                    #[allow(non_upper_case_globals)]
+                    const mocktopus_warning: () = ();
+                    #warning_use
+                };
+            }
+        });
+        quote!(#(#errors)* #(#warnings)*)
+    }
+}
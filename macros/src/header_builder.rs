@@ -1,19 +1,19 @@
-use crate::display_delegate::display;
-use proc_macro2::{Group, Span, TokenTree};
-use quote::ToTokens;
-use std::fmt::{Error, Formatter};
+use crate::type_tools;
+use proc_macro2::{Delimiter, Group, Span, TokenStream, TokenTree};
+use quote::{quote, quote_spanned, ToTokens};
 use syn::punctuated::Punctuated;
-use syn::token::{Colon2, Semi};
+use syn::token::Colon2;
 use syn::{
-    self, Expr, ExprUnsafe, FnArg, GenericParam, Pat, PatIdent, PatType, PathSegment, Signature,
-    Stmt,
+    parse_quote, Attribute, Block, FnArg, GenericParam, Ident, Index, Pat, PatIdent, PatType,
+    PathSegment, ReturnType, Signature, Type,
 };
 
 const MOCKTOPUS_CRATE_NAME: &str = "__mocktopus_crate__";
-const STD_CRATE_NAME: &str = "__mocktopus_std__";
 const ARGS_TO_CONTINUE_NAME: &str = "__mocktopus_args_to_continue__";
 const ARGS_TO_RETURN_NAME: &str = "__mocktopus_args_to_return__";
-const UNWIND_DATA_NAME: &str = "__mocktopus_unwind_data__";
+const THEN_NAME: &str = "__mocktopus_then__";
+const RESULT_NAME: &str = "__mocktopus_result__";
+const DEBUG_ARGS_NAME: &str = "__mocktopus_debug_args__";
 
 macro_rules! error_msg {
     ($msg:expr) => {
@@ -29,50 +29,131 @@ pub enum FnHeaderBuilder<'a> {
 }
 
 impl<'a> FnHeaderBuilder<'a> {
-    pub fn build(&self, fn_decl: &Signature, fn_block_span: Span) -> Stmt {
+    /// Injects mock call before the function body and wraps the body,
+    /// so its result can be passed through a hook returned from the mock.
+    ///
+    /// Bodies of functions returning `!` or annotated with `#[track_caller]` are not wrapped,
+    /// because the closure would need `!` as a type argument or would hide the caller location.
+    pub fn build(&self, attrs: &[Attribute], fn_decl: &Signature, block: &mut Block) {
         let fn_args = &fn_decl.inputs;
-        let header_str = format!(
-            r#"unsafe {{
-                extern crate mocktopus as {mocktopus};
-                extern crate std as {std_crate};
-                match {std_crate}::panic::catch_unwind({std_crate}::panic::AssertUnwindSafe (
-                        || {mocktopus}::mocking::Mockable::call_mock(&{full_fn_name}, {extract_args}))) {{
-                    Ok({mocktopus}::mocking::MockResult::Continue(mut {args_to_continue})) => {restore_args},
-                    Ok({mocktopus}::mocking::MockResult::Return({args_to_return})) => {{
-                        {forget_args}
-                        let returned = {std_crate}::mem::transmute_copy(&{args_to_return});
-                        {std_crate}::mem::forget({args_to_return});
-                        return returned;
-                    }},
-                    Err({unwind}) => {{
-                        {forget_args}
-                        {std_crate}::panic::resume_unwind({unwind});
-                    }},
-                }}
-            }}"#,
-            mocktopus = MOCKTOPUS_CRATE_NAME,
-            std_crate = STD_CRATE_NAME,
-            full_fn_name = display(|f| write_full_fn_name(f, self, fn_decl)),
-            extract_args = display(|f| write_extract_args(f, fn_args)),
-            args_to_continue = ARGS_TO_CONTINUE_NAME,
-            args_to_return = ARGS_TO_RETURN_NAME,
-            restore_args = display(|f| write_restore_args(f, fn_args)),
-            forget_args = display(|f| write_forget_args(f, fn_args)),
-            unwind = UNWIND_DATA_NAME
+        let mocktopus = Ident::new(MOCKTOPUS_CRATE_NAME, Span::call_site());
+        let args_to_continue = Ident::new(ARGS_TO_CONTINUE_NAME, Span::call_site());
+        let args_to_return = Ident::new(ARGS_TO_RETURN_NAME, Span::call_site());
+        let then = Ident::new(THEN_NAME, Span::call_site());
+        let result = Ident::new(RESULT_NAME, Span::call_site());
+        let full_fn_name = self.full_fn_name(fn_decl);
+        let extract_args = extract_args(fn_args);
+        let restore_args = restore_args(fn_args);
+        let debug_args = debug_args(fn_args);
+        let returns_never = match fn_decl.output {
+            ReturnType::Type(_, ref ty) => matches!(**ty, Type::Never(_)),
+            ReturnType::Default => false,
+        };
+        // Output type of `!` would be inferred by fallback, which is denied in unsafe calls
+        let (intercept_output, return_mock_result) = match returns_never {
+            true => (
+                quote!(#mocktopus::__private::Never),
+                quote!(match #args_to_return {}),
+            ),
+            false => (
+                quote!(_),
+                quote!(return #mocktopus::mocking::cast(#args_to_return);),
+            ),
+        };
+        let span = block.brace_token.span;
+        let intercept = make_span_call_site(
+            quote! {
+                extern crate mocktopus as #mocktopus;
+                #[allow(clippy::all, forgetting_copy_types, forgetting_references)]
+                let #then = unsafe {
+                    match #mocktopus::__private::intercept::<_, _, #intercept_output>(
+                        &#full_fn_name,
+                        #extract_args,
+                        #debug_args,
+                    ) {
+                        #mocktopus::__private::Intercepted::Continue(#args_to_continue, #then) => {
+                            #restore_args
+                            #then
+                        }
+                        #mocktopus::__private::Intercepted::Return(#args_to_return) => {
+                            #return_mock_result
+                        }
+                    }
+                };
+            },
+            span,
         );
-        let header_block = syn::parse_str::<ExprUnsafe>(&header_str)
-            .expect(error_msg!("generated header unparsable"));
-        create_call_site_spanned_stmt(header_block, fn_block_span)
+        let stmts = &block.stmts;
+        let brace_token = block.brace_token;
+        if returns_never || is_track_caller(attrs) {
+            let reject_then =
+                make_span_call_site(quote!(#mocktopus::mocking::reject_then(#then);), span);
+            *block = parse_quote!({ #intercept #reject_then #(#stmts)* });
+            block.brace_token = brace_token;
+            return;
+        }
+        let output = match fn_decl.output {
+            ReturnType::Type(_, ref ty)
+                if !type_tools::contains_ident(ty.to_token_stream(), "impl") =>
+            {
+                ty.to_token_stream()
+            }
+            ReturnType::Type(..) => quote!(_),
+            ReturnType::Default => quote!(()),
+        };
+        let header = make_span_call_site(
+            quote! {
+                #[allow(clippy::all)]
+                let #result = #mocktopus::mocking::run_original::<#output, _>
+            },
+            span,
+        );
+        let mut body = Group::new(Delimiter::Brace, quote!(#(#stmts)*));
+        body.set_span(span);
+        // Body is not respanned to keep its error messages pointing at the original code
+        let mut body_closure = make_span_call_site(quote!(move ||), span);
+        body_closure.extend(Some(TokenTree::Group(body)));
+        let mut body_call = Group::new(Delimiter::Parenthesis, body_closure);
+        body_call.set_span(span);
+        let body_call_stmt = quote_spanned!(span=> #body_call;);
+        let footer = make_span_call_site(
+            quote! {
+                match #then {
+                    Some(#then) => unsafe { #mocktopus::mocking::run_then(#then, #result) },
+                    None => #result,
+                }
+            },
+            span,
+        );
+        *block = parse_quote!({ #intercept #header #body_call_stmt #footer });
+        block.brace_token = brace_token;
+    }
+
+    fn full_fn_name(&self, fn_decl: &Signature) -> TokenStream {
+        let prefix = match *self {
+            FnHeaderBuilder::StaticFn => quote!(),
+            FnHeaderBuilder::StructImpl | FnHeaderBuilder::TraitDefault => quote!(Self::),
+            FnHeaderBuilder::TraitImpl(path) => quote!(<Self as #path>::),
+        };
+        let ident = &fn_decl.ident;
+        let generics = fn_decl
+            .generics
+            .params
+            .iter()
+            .filter_map(get_generic_param_name);
+        quote!(#prefix #ident::<#(#generics,)*>)
     }
 }
 
-fn create_call_site_spanned_stmt(block: impl ToTokens, span: Span) -> Stmt {
-    let token_stream = block
-        .into_token_stream()
+fn is_track_caller(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| attr.path.is_ident("track_caller"))
+}
+
+fn make_span_call_site(tokens: TokenStream, span: Span) -> TokenStream {
+    tokens
         .into_iter()
         .map(|tt| make_token_tree_span_call_site(tt, span))
-        .collect();
-    Stmt::Semi(Expr::Verbatim(token_stream), Semi { spans: [span] })
+        .collect()
 }
 
 fn make_token_tree_span_call_site(mut token_tree: TokenTree, span: Span) -> TokenTree {
@@ -88,101 +169,65 @@ fn make_token_tree_span_call_site(mut token_tree: TokenTree, span: Span) -> Toke
     token_tree
 }
 
-fn write_full_fn_name(
-    f: &mut Formatter,
-    builder: &FnHeaderBuilder,
-    fn_decl: &Signature,
-) -> Result<(), Error> {
-    match *builder {
-        FnHeaderBuilder::StaticFn => (),
-        FnHeaderBuilder::StructImpl | FnHeaderBuilder::TraitDefault => write!(f, "Self::")?,
-        FnHeaderBuilder::TraitImpl(ref path) => {
-            write!(f, "<Self as {}>::", display(|f| write_trait_path(f, path)))?
-        }
-    }
-    write!(
-        f,
-        "{}::<{}>",
-        fn_decl.ident,
-        display(|f| write_fn_generics(f, fn_decl))
-    )
-}
-
-fn write_trait_path<T: ToTokens + Clone>(
-    f: &mut Formatter,
-    path: &Punctuated<PathSegment, T>,
-) -> Result<(), Error> {
-    write!(f, "{}", path.into_token_stream())
-}
-
-fn write_fn_generics(f: &mut Formatter, fn_decl: &Signature) -> Result<(), Error> {
-    fn_decl
-        .generics
-        .params
-        .iter()
-        .filter_map(get_generic_param_name)
-        .map(|param| write!(f, "{},", param))
-        .collect()
-}
-
-fn get_generic_param_name(param: &GenericParam) -> Option<String> {
+fn get_generic_param_name(param: &GenericParam) -> Option<&Ident> {
     match *param {
-        GenericParam::Type(ref type_param) => Some(type_param.ident.to_string()),
-        _ => None,
+        GenericParam::Type(ref type_param) => Some(&type_param.ident),
+        GenericParam::Const(ref const_param) => Some(&const_param.ident),
+        GenericParam::Lifetime(_) => None,
     }
 }
 
-fn write_extract_args<T>(f: &mut Formatter, fn_args: &Punctuated<FnArg, T>) -> Result<(), Error> {
+fn extract_args<T>(fn_args: &Punctuated<FnArg, T>) -> TokenStream {
     if fn_args.is_empty() {
-        return write!(f, "()");
+        return quote!(());
     }
-    write!(f, "(")?;
-    for fn_arg_name in iter_fn_arg_names(fn_args) {
-        write!(
-            f,
-            "{}::mem::transmute_copy(&{}), ",
-            STD_CRATE_NAME, fn_arg_name
-        )?;
-    }
-    write!(f, ")")
+    let mocktopus = Ident::new(MOCKTOPUS_CRATE_NAME, Span::call_site());
+    let fn_arg_names = iter_fn_arg_names(fn_args);
+    quote!(#mocktopus::mocking::cast((#(#fn_arg_names,)*)))
 }
 
-fn write_restore_args<T>(f: &mut Formatter, fn_args: &Punctuated<FnArg, T>) -> Result<(), Error> {
+/// Builds a closure formatting arguments for messages of panicking mocks. It picks arguments,
+/// which are `Debug`, with autoref specialization on their concrete types.
+fn debug_args<T>(fn_args: &Punctuated<FnArg, T>) -> TokenStream {
     if fn_args.is_empty() {
-        return writeln!(f, "()");
+        return quote!(&|_| None);
     }
-    writeln!(f, "{{")?;
-    for (fn_arg_index, fn_arg_name) in iter_fn_arg_names(fn_args).enumerate() {
-        writeln!(
-            f,
-            "{}::mem::swap(&mut *(&{} as *const _ as *mut _), &mut {}.{});",
-            STD_CRATE_NAME, fn_arg_name, ARGS_TO_CONTINUE_NAME, fn_arg_index
-        )?;
+    let mocktopus = Ident::new(MOCKTOPUS_CRATE_NAME, Span::call_site());
+    let debug_args = Ident::new(DEBUG_ARGS_NAME, Span::call_site());
+    let indexes = (0..fn_args.len()).map(Index::from);
+    quote! {
+        &|#debug_args| {
+            #[allow(unused_imports)]
+            use #mocktopus::__private::{DebugArg as _, SkipDebugArg as _};
+            #mocktopus::__private::format_debug_args(&[
+                #((&#mocktopus::__private::ArgProbe(&#debug_args.#indexes)).debug_arg(),)*
+            ])
+        }
     }
-    writeln!(
-        f,
-        "{}::mem::forget({});",
-        STD_CRATE_NAME, ARGS_TO_CONTINUE_NAME
-    )?;
-    writeln!(f, "}}")
 }
 
-fn write_forget_args<T>(f: &mut Formatter, fn_args: &Punctuated<FnArg, T>) -> Result<(), Error> {
-    for fn_arg_name in iter_fn_arg_names(fn_args) {
-        writeln!(f, "{}::mem::forget({});", STD_CRATE_NAME, fn_arg_name)?;
+/// Moves arguments back into their bindings, which are made mutable by the injector
+fn restore_args<T>(fn_args: &Punctuated<FnArg, T>) -> TokenStream {
+    if fn_args.is_empty() {
+        return quote!();
     }
-    Ok(())
+    let mocktopus = Ident::new(MOCKTOPUS_CRATE_NAME, Span::call_site());
+    let args_to_continue = Ident::new(ARGS_TO_CONTINUE_NAME, Span::call_site());
+    let fn_arg_names = iter_fn_arg_names(fn_args);
+    quote!((#(#fn_arg_names,)*) = #mocktopus::mocking::cast(#args_to_continue);)
 }
 
 fn iter_fn_arg_names<'a, T>(
     input_args: &'a Punctuated<FnArg, T>,
-) -> impl Iterator<Item = String> + 'a {
+) -> impl Iterator<Item = Ident> + 'a {
     input_args.iter().map(|fn_arg| {
         match fn_arg {
-            FnArg::Receiver(_) => return "self".to_string(),
+            FnArg::Receiver(_) => return Ident::new("self", Span::call_site()),
             FnArg::Typed(PatType { pat, .. }) => {
                 if let Pat::Ident(PatIdent { ident, .. }) = &**pat {
-                    return ident.to_string();
+                    let mut ident = ident.clone();
+                    ident.set_span(Span::call_site());
+                    return ident;
                 }
             }
         };
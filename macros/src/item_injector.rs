@@ -1,8 +1,11 @@
+use crate::diagnostics::Diagnostics;
 use crate::header_builder::FnHeaderBuilder;
+use crate::{args_builder, mocks_builder, registry_builder, substitute_builder, type_tools};
 use proc_macro2::{TokenStream, TokenTree};
 use quote::{format_ident, quote, quote_spanned, ToTokens};
 use std::iter::FromIterator;
 use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
 use syn::token::Comma;
 use syn::{
     parse_quote, Attribute, Block, FnArg, GenericParam, Generics, Ident, ImplItem, ImplItemMethod,
@@ -12,7 +15,9 @@ use syn::{
 
 #[derive(Clone, Copy)]
 enum Context<'a> {
-    Trait,
+    Trait {
+        trait_ident: &'a Ident,
+    },
     Impl {
         receiver: &'a Type,
         impl_generics: &'a Generics,
@@ -20,60 +25,114 @@ enum Context<'a> {
     Fn,
 }
 
-pub fn inject_item(item: &mut Item) {
+/// Optional companion items of an inherent impl block, requested in `mockable` attribute arguments
+#[derive(Clone, Copy, Default)]
+pub struct ImplCompanions {
+    /// `{Type}Api` trait and `Substitutable` impl
+    pub substitute: bool,
+    /// `{Type}Mocks` builder
+    pub mocks: bool,
+}
+
+/// Makes item mockable and returns companion items, which must be placed next to it
+pub fn inject_item(
+    item: &mut Item,
+    impl_companions: ImplCompanions,
+    diagnostics: &mut Diagnostics,
+) -> Vec<Item> {
     match *item {
-        Item::Fn(ref mut item_fn) => inject_fn(item_fn),
-        Item::Mod(ref mut item_mod) => inject_mod(item_mod),
-        Item::Trait(ref mut item_trait) => inject_trait(item_trait),
-        Item::Impl(ref mut item_impl) => inject_impl(item_impl),
+        Item::Fn(ref mut item_fn) => return inject_fn(item_fn, diagnostics),
+        Item::Mod(ref mut item_mod) => inject_mod(item_mod, diagnostics),
+        Item::Trait(ref mut item_trait) => inject_trait(item_trait, diagnostics),
+        Item::Impl(ref mut item_impl) => {
+            return inject_impl(item_impl, impl_companions, diagnostics)
+        }
+        Item::Macro(ref item_macro) if item_macro.ident.is_none() => diagnostics.skipped(
+            item_macro.mac.path.span(),
+            "items generated by macros can't be made mockable",
+        ),
         _ => (),
     }
+    Vec::new()
 }
 
-fn inject_fn(item_fn: &mut ItemFn) {
+fn inject_fn(item_fn: &mut ItemFn, diagnostics: &mut Diagnostics) -> Vec<Item> {
+    let companions = match is_fn_injectable(&item_fn.attrs, &item_fn.sig) {
+        true => args_builder::build_for_fn(&cfg_attrs(&item_fn.attrs), &item_fn.vis, &item_fn.sig)
+            .unwrap_or_default(),
+        false => Vec::new(),
+    };
     inject_any_fn(
         Context::Fn,
         &FnHeaderBuilder::StaticFn,
         &item_fn.attrs,
         &mut item_fn.sig,
-        &mut *item_fn.block,
+        &mut item_fn.block,
+        diagnostics,
     );
+    companions
 }
 
-fn inject_mod(item_mod: &mut ItemMod) {
+fn inject_mod(item_mod: &mut ItemMod, diagnostics: &mut Diagnostics) {
     if is_not_mockable(&item_mod.attrs) {
         return;
     }
-    item_mod
-        .content
-        .iter_mut()
-        .flat_map(|c| &mut c.1)
-        .for_each(inject_item)
+    match item_mod.content {
+        Some((_, ref mut items)) => {
+            let companions: Vec<_> = items
+                .iter_mut()
+                .flat_map(|item| inject_item(item, ImplCompanions::default(), diagnostics))
+                .collect();
+            items.extend(companions);
+        }
+        None => diagnostics.skipped(
+            item_mod.ident.span(),
+            "module declarations can't be made mockable, annotate items inside the module file",
+        ),
+    }
 }
 
-fn inject_trait(item_trait: &mut ItemTrait) {
+fn inject_trait(item_trait: &mut ItemTrait, diagnostics: &mut Diagnostics) {
     if is_not_mockable(&item_trait.attrs) {
         return;
     }
 
-    let context = Context::Trait;
+    let context = Context::Trait {
+        trait_ident: &item_trait.ident,
+    };
     for item in &mut item_trait.items {
-        if let TraitItem::Method(TraitItemMethod {
-            ref attrs,
-            ref mut sig,
-            default: Some(ref mut block),
-            ..
-        }) = *item
-        {
-            inject_any_fn(context, &FnHeaderBuilder::TraitDefault, attrs, sig, block);
+        match *item {
+            TraitItem::Method(TraitItemMethod {
+                ref attrs,
+                ref mut sig,
+                default: Some(ref mut block),
+                ..
+            }) => inject_any_fn(
+                context,
+                &FnHeaderBuilder::TraitDefault,
+                attrs,
+                sig,
+                block,
+                diagnostics,
+            ),
+            TraitItem::Macro(ref trait_item_macro) => diagnostics.skipped(
+                trait_item_macro.mac.path.span(),
+                "items generated by macros can't be made mockable",
+            ),
+            _ => (),
         }
     }
 }
 
-fn inject_impl(item_impl: &mut ItemImpl) {
+fn inject_impl(
+    item_impl: &mut ItemImpl,
+    impl_companions: ImplCompanions,
+    diagnostics: &mut Diagnostics,
+) -> Vec<Item> {
     if is_not_mockable(&item_impl.attrs) {
-        return;
+        return Vec::new();
     }
+    let companions = build_impl_companions(item_impl, impl_companions, diagnostics);
     let builder = match item_impl.trait_ {
         Some((_, ref path, _)) => FnHeaderBuilder::TraitImpl(&path.segments),
         None => FnHeaderBuilder::StructImpl,
@@ -85,27 +144,90 @@ fn inject_impl(item_impl: &mut ItemImpl) {
     };
 
     for impl_item in &mut item_impl.items {
-        if let ImplItem::Method(ref mut item_method) = *impl_item {
-            if is_impl_fn_mockabile(&builder, item_method) {
-                inject_any_fn(
-                    context,
-                    &builder,
-                    &item_method.attrs,
-                    &mut item_method.sig,
-                    &mut item_method.block,
-                );
+        match *impl_item {
+            ImplItem::Method(ref mut item_method) => {
+                if is_impl_fn_mockabile(&builder, item_method) {
+                    inject_any_fn(
+                        context,
+                        &builder,
+                        &item_method.attrs,
+                        &mut item_method.sig,
+                        &mut item_method.block,
+                        diagnostics,
+                    );
+                } else if !is_not_mockable(&item_method.attrs) {
+                    diagnostics
+                        .skipped(item_method.sig.ident.span(), "`Drop::drop` can't be mocked");
+                }
+            }
+            ImplItem::Macro(ref impl_item_macro) => diagnostics.skipped(
+                impl_item_macro.mac.path.span(),
+                "items generated by macros can't be made mockable",
+            ),
+            _ => (),
+        }
+    }
+    companions
+}
+
+fn build_impl_companions(
+    item_impl: &ItemImpl,
+    impl_companions: ImplCompanions,
+    diagnostics: &mut Diagnostics,
+) -> Vec<Item> {
+    let mockable_methods: Vec<_> = item_impl
+        .items
+        .iter()
+        .filter_map(|impl_item| match *impl_item {
+            ImplItem::Method(ref item_method)
+                if is_fn_injectable(&item_method.attrs, &item_method.sig) =>
+            {
+                Some(item_method)
             }
+            _ => None,
+        })
+        .collect();
+    let type_ident = type_tools::get_impl_type_ident(item_impl);
+    if (impl_companions.substitute || impl_companions.mocks) && type_ident.is_none() {
+        diagnostics.error(
+            item_impl.self_ty.span(),
+            "`substitute` and `mocks` are only supported for non-generic impl blocks of plain types",
+        );
+    }
+    let mut companions = Vec::new();
+    if impl_companions.substitute {
+        companions.extend(substitute_builder::build(item_impl, &mockable_methods));
+    }
+    if impl_companions.mocks {
+        companions.extend(mocks_builder::build(item_impl, &mockable_methods));
+    }
+    if let Some(type_ident) = type_ident {
+        for item_method in mockable_methods {
+            let mut method_cfg_attrs = cfg_attrs(&item_impl.attrs);
+            method_cfg_attrs.extend(cfg_attrs(&item_method.attrs));
+            companions.extend(
+                args_builder::build_for_method(
+                    type_ident,
+                    &item_impl.self_ty,
+                    &method_cfg_attrs,
+                    &item_method.vis,
+                    &item_method.sig,
+                )
+                .unwrap_or_default(),
+            );
         }
     }
+    companions
 }
 
 fn is_impl_fn_mockabile(builder: &FnHeaderBuilder, item_method: &ImplItemMethod) -> bool {
-    if let FnHeaderBuilder::TraitImpl(ref segments) = *builder {
+    if let FnHeaderBuilder::TraitImpl(segments) = *builder {
         if let Some(segment) = segments.last() {
-            if segment.arguments.is_empty() && segment.ident == "Drop" {
-                if item_method.sig.ident == "drop" {
-                    return false;
-                }
+            if segment.arguments.is_empty()
+                && segment.ident == "Drop"
+                && item_method.sig.ident == "drop"
+            {
+                return false;
             }
         }
     }
@@ -115,32 +237,83 @@ fn is_impl_fn_mockabile(builder: &FnHeaderBuilder, item_method: &ImplItemMethod)
 fn inject_any_fn(
     context: Context,
     builder: &FnHeaderBuilder,
-    attrs: &Vec<Attribute>,
+    attrs: &[Attribute],
     fn_decl: &mut Signature,
     block: &mut Block,
+    diagnostics: &mut Diagnostics,
 ) {
-    if fn_decl.constness.is_some()
-        || fn_decl.unsafety.is_some()
-        || fn_decl.variadic.is_some()
-        || is_not_mockable(attrs)
-    {
+    if !is_fn_injectable(attrs, fn_decl) {
+        report_not_injectable_fn(attrs, fn_decl, diagnostics);
         return;
     }
 
-    if let Some(_) = fn_decl.asyncness {
+    let registration = build_registration(context, builder, fn_decl);
+    if fn_decl.asyncness.is_some() {
         inject_async_fn(context, attrs, fn_decl, block);
     }
 
     unignore_fn_args(&mut fn_decl.inputs);
-    let header_stmt = builder.build(fn_decl, block.brace_token.span);
-    block.stmts.insert(0, header_stmt);
+    make_fn_args_mutable(&mut fn_decl.inputs);
+    builder.build(attrs, fn_decl, block);
+    if !registration.is_empty() {
+        block.stmts.insert(0, parse_quote!(#registration));
+    }
+}
+
+fn build_registration(
+    context: Context,
+    builder: &FnHeaderBuilder,
+    fn_decl: &Signature,
+) -> TokenStream {
+    let (self_ty, trait_ident) = match context {
+        Context::Fn => (None, None),
+        Context::Trait { trait_ident } => (None, Some(trait_ident)),
+        Context::Impl {
+            receiver,
+            impl_generics,
+        } => {
+            let self_ty = registry_builder::get_self_ty_ident(receiver, impl_generics);
+            let trait_ident = match *builder {
+                FnHeaderBuilder::TraitImpl(segments) => {
+                    segments.last().map(|segment| &segment.ident)
+                }
+                _ => None,
+            };
+            (self_ty, trait_ident)
+        }
+    };
+    registry_builder::build(self_ty, trait_ident, fn_decl)
+}
+
+fn is_fn_injectable(attrs: &[Attribute], fn_decl: &Signature) -> bool {
+    fn_decl.constness.is_none()
+        && fn_decl.unsafety.is_none()
+        && fn_decl.variadic.is_none()
+        && !is_not_mockable(attrs)
+}
+
+fn report_not_injectable_fn(
+    attrs: &[Attribute],
+    fn_decl: &Signature,
+    diagnostics: &mut Diagnostics,
+) {
+    if is_not_mockable(attrs) {
+        return;
+    }
+    if let Some(ref constness) = fn_decl.constness {
+        diagnostics.skipped(constness.span, "const functions can't be mocked");
+    } else if let Some(ref unsafety) = fn_decl.unsafety {
+        diagnostics.skipped(unsafety.span, "unsafe functions can't be mocked");
+    } else if let Some(ref variadic) = fn_decl.variadic {
+        diagnostics.skipped(variadic.span(), "variadic functions can't be mocked");
+    }
 }
 
 // Transform async functions as `async-trait`
 // See: https://github.com/dtolnay/async-trait
 fn inject_async_fn(
     context: Context,
-    attrs: &Vec<Attribute>,
+    attrs: &[Attribute],
     outer_sig: &mut Signature,
     block: &mut Block,
 ) {
@@ -197,13 +370,10 @@ fn inject_async_fn(
                 _ => unreachable!(),
             };
             let under_self = Ident::new("_self", self_token.span);
-            match context {
-                Context::Impl { receiver, .. } => {
-                    *arg = parse_quote! {
-                        #under_self: &#lifetime #mutability #receiver
-                    };
-                }
-                _ => (),
+            if let Context::Impl { receiver, .. } = context {
+                *arg = parse_quote! {
+                    #under_self: &#lifetime #mutability #receiver
+                };
             };
         }
         Some(arg @ FnArg::Receiver(_)) => {
@@ -216,13 +386,10 @@ fn inject_async_fn(
                 _ => unreachable!(),
             };
             let under_self = Ident::new("_self", self_token.span);
-            match context {
-                Context::Impl { receiver, .. } => {
-                    *arg = parse_quote! {
-                        #under_self: #mutability #receiver
-                    };
-                }
-                _ => (),
+            if let Context::Impl { receiver, .. } = context {
+                *arg = parse_quote! {
+                    #under_self: #mutability #receiver
+                };
             };
         }
         _ => {}
@@ -233,8 +400,10 @@ fn inject_async_fn(
     }
 
     // this is the standalone async fn
+    let mut inner_attrs = attrs.to_vec();
+    inner_attrs.push(parse_quote!(#[allow(clippy::extra_unused_type_parameters)]));
     let inner_fn = ItemFn {
-        attrs: attrs.clone(),
+        attrs: inner_attrs,
         vis: Visibility::Inherited,
         sig: inner_sig,
         block: Box::new(block.clone()),
@@ -259,11 +428,9 @@ fn inject_async_fn(
             predicates: Punctuated::new(),
         });
 
-    let mut outer_sig_inputs = outer_sig.inputs.iter_mut();
-    while let Some(input) = outer_sig_inputs.next() {
+    for input in outer_sig.inputs.iter_mut() {
         match input {
-            arg
-            @ FnArg::Receiver(Receiver {
+            arg @ FnArg::Receiver(Receiver {
                 reference: Some(_), ..
             }) => {
                 let (self_token, mutability) = match arg {
@@ -364,20 +531,58 @@ fn unignore_fn_args(inputs: &mut Punctuated<FnArg, Comma>) {
                 }
                 _ => continue,
             };
-            *pat = Box::new(Pat::Ident(PatIdent {
+            **pat = Pat::Ident(PatIdent {
                 by_ref: None,
                 mutability: None,
                 ident: Ident::new(&format!("__mocktopus_unignored_argument_{}__", i), span),
                 subpat: None,
                 attrs,
-            }));
+            });
+        }
+    }
+}
+
+/// Makes arguments mutable, so the header can move them out to the mock and back in
+fn make_fn_args_mutable(inputs: &mut Punctuated<FnArg, Comma>) {
+    for fn_arg in inputs.iter_mut() {
+        if let FnArg::Receiver(Receiver {
+            ref attrs,
+            ref reference,
+            mutability,
+            self_token,
+        }) = *fn_arg
+        {
+            *fn_arg = match reference {
+                Some((and_token, lifetime)) => {
+                    parse_quote!(#(#attrs)* #self_token: #and_token #lifetime #mutability Self)
+                }
+                None => parse_quote!(#(#attrs)* #mutability #self_token: Self),
+            };
+        }
+        if let FnArg::Typed(PatType {
+            ref mut attrs,
+            ref mut pat,
+            ..
+        }) = *fn_arg
+        {
+            if let Pat::Ident(PatIdent {
+                by_ref: None,
+                ref mut mutability,
+                ..
+            }) = **pat
+            {
+                if mutability.is_none() {
+                    *mutability = Some(Default::default());
+                    attrs.push(parse_quote!(#[allow(unused_mut)]));
+                }
+            }
         }
     }
 }
 
 const INJECTOR_STOPPER_ATTRS: [&str; 2] = ["mockable", "not_mockable"];
 
-fn is_not_mockable(attrs: &Vec<Attribute>) -> bool {
+fn is_not_mockable(attrs: &[Attribute]) -> bool {
     attrs
         .iter()
         .filter_map(|a| a.path.segments.last())
@@ -385,6 +590,15 @@ fn is_not_mockable(attrs: &Vec<Attribute>) -> bool {
         .any(|i| INJECTOR_STOPPER_ATTRS.contains(&&*i))
 }
 
+/// Returns `cfg` attributes, which companion items must copy to be compiled only with their item
+fn cfg_attrs(attrs: &[Attribute]) -> Vec<Attribute> {
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("cfg"))
+        .cloned()
+        .collect()
+}
+
 fn positional_arg(i: usize) -> Ident {
     format_ident!("__arg{}", i)
 }
@@ -567,8 +781,7 @@ fn replace_self_in_path(path: &mut syn::Path) {
 
 fn replace_self_in_token_stream(tokens: &mut TokenStream) {
     let mut out = Vec::new();
-    let mut iter = tokens.clone().into_iter().peekable();
-    while let Some(tt) = iter.next() {
+    for tt in tokens.clone().into_iter() {
         match tt {
             TokenTree::Ident(mut ident) => {
                 if ident == "self" {
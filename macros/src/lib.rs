@@ -3,16 +3,27 @@
     html_logo_url = "https://raw.githubusercontent.com/CodeSandwich/mocktopus/master/logo.png",
     html_favicon_url = "https://raw.githubusercontent.com/CodeSandwich/mocktopus/master/logo.png"
 )]
-#![feature(proc_macro_diagnostic)]
 
 extern crate proc_macro;
 
-mod display_delegate;
+mod args_builder;
+mod diagnostics;
 mod header_builder;
 mod item_injector;
+mod mocks_builder;
+mod registry_builder;
+mod substitute_builder;
+mod test_builder;
+mod type_tools;
 
-use proc_macro::{Span, TokenStream};
+use diagnostics::Diagnostics;
+use item_injector::ImplCompanions;
+use proc_macro::TokenStream;
+use proc_macro2::{Ident, Span};
 use quote::ToTokens;
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::Token;
 
 /// Procedural macro, makes items and their sub-items mockable
 ///
@@ -33,6 +44,10 @@ use quote::ToTokens;
 /// #[mockable]
 /// fn mockable() { ... }
 /// ```
+/// For non-generic functions a `mockable_args` struct with fields named after the arguments is generated
+/// next to the function, see [`Mockable::mock_named`](https://docs.rs/mocktopus/*/mocktopus/mocking/trait.Mockable.html#tymethod.mock_named).
+/// For methods of non-generic impl blocks it's named `Structure_mockable_args`.
+///
 /// - struct impl blocks (makes all functions inside mockable)
 ///
 /// ```
@@ -41,6 +56,19 @@ use quote::ToTokens;
 ///     fn mockable() { ... }
 /// }
 /// ```
+/// Arguments request items generated next to a non-generic impl block,
+/// they can be used on only one impl block of each type:
+/// - `substitute` generates a `StructureApi` trait,
+///   see [`MockContext::substitute`](https://docs.rs/mocktopus/*/mocktopus/mocking/struct.MockContext.html#method.substitute)
+/// - `mocks` generates a `StructureMocks` builder with a typed setter for every method
+///
+/// ```
+/// #[mockable(substitute, mocks)]
+/// impl Structure {
+///     fn mockable() { ... }
+/// }
+/// ```
+///
 /// - trait impl blocks (makes all functions inside mockable)
 ///
 /// ```
@@ -95,20 +123,81 @@ use quote::ToTokens;
 /// - unsafe functions (they are impossible to mock)
 /// - any macro generated items (they are impossible to mock)
 /// - any other items
+///
+/// With `warn-skipped` feature enabled a warning is emitted for every function or item, which is skipped,
+/// because it's impossible to mock, unless it's annotated with `#[not_mockable]`.
 #[proc_macro_attribute]
-pub fn mockable(_: TokenStream, token_stream: TokenStream) -> TokenStream {
+pub fn mockable(attr: TokenStream, token_stream: TokenStream) -> TokenStream {
+    let mut diagnostics = Diagnostics::default();
     let mut item: syn::Item = match syn::parse(token_stream.clone()) {
         Ok(item) => item,
         Err(err) => {
-            Span::call_site()
-                .warning("Failed to make code mockable")
-                .error(format!("Failed to parse: {}", err))
-                .emit();
-            return token_stream;
+            diagnostics.error(
+                err.span(),
+                format!("Failed to make code mockable, failed to parse: {}", err),
+            );
+            let mut output = proc_macro2::TokenStream::from(token_stream);
+            output.extend(diagnostics.into_token_stream());
+            return output.into();
+        }
+    };
+    if !is_injectable_item(&item) {
+        diagnostics.skipped(
+            Span::call_site(),
+            "only functions, modules, traits and impl blocks can be made mockable",
+        );
+    }
+    let impl_companions = parse_impl_companions(attr.into(), &item, &mut diagnostics);
+    let companions = item_injector::inject_item(&mut item, impl_companions, &mut diagnostics);
+    let mut output = item.into_token_stream();
+    output.extend(companions.into_iter().map(ToTokens::into_token_stream));
+    output.extend(diagnostics.into_token_stream());
+    output.into()
+}
+
+/// Parses `mockable` attribute arguments requesting companion items of an inherent impl block
+fn parse_impl_companions(
+    attr: proc_macro2::TokenStream,
+    item: &syn::Item,
+    diagnostics: &mut Diagnostics,
+) -> ImplCompanions {
+    let mut impl_companions = ImplCompanions::default();
+    if attr.is_empty() {
+        return impl_companions;
+    }
+    let args = match Punctuated::<Ident, Token![,]>::parse_terminated.parse2(attr) {
+        Ok(args) => args,
+        Err(err) => {
+            diagnostics.error(err.span(), format!("Failed to parse arguments: {}", err));
+            return impl_companions;
         }
     };
-    item_injector::inject_item(&mut item);
-    item.into_token_stream().into()
+    for arg in args {
+        match arg.to_string().as_str() {
+            "substitute" => impl_companions.substitute = true,
+            "mocks" => impl_companions.mocks = true,
+            _ => diagnostics.error(
+                arg.span(),
+                "unknown argument, expected `substitute` or `mocks`",
+            ),
+        }
+    }
+    if !matches!(item, syn::Item::Impl(syn::ItemImpl { trait_: None, .. })) {
+        diagnostics.error(
+            Span::call_site(),
+            "mockable arguments are only valid on inherent impl blocks",
+        );
+    }
+    impl_companions
+}
+
+/// Checks if item is handled by the injector, which itself reports skipped macro invocations
+fn is_injectable_item(item: &syn::Item) -> bool {
+    match item {
+        syn::Item::Fn(_) | syn::Item::Mod(_) | syn::Item::Trait(_) | syn::Item::Impl(_) => true,
+        syn::Item::Macro(item_macro) => item_macro.ident.is_none(),
+        _ => false,
+    }
 }
 
 /// Procedural macro, guards items from being made mockable by enclosing item.
@@ -202,3 +291,82 @@ pub fn mockable(_: TokenStream, token_stream: TokenStream) -> TokenStream {
 pub fn not_mockable(_: TokenStream, token_stream: TokenStream) -> TokenStream {
     token_stream
 }
+
+/// Procedural macro, makes a test function isolated from mocks of other tests,
+/// use it as `#[mocktopus::test]`.
+///
+/// It's needed when tests share threads, e.g. under custom harnesses, and replaces `#[test]`:
+///
+/// ```
+/// #[mocktopus::test]
+/// fn add_test() {
+///     add.mock_safe(|a, b| MockResult::Return(a * b));
+///
+///     assert_eq!(6, add(2, 3));
+/// }
+/// ```
+/// Mocks of the thread are cleared before and after the test. If there were any before the test,
+/// it fails. The body runs inside a fresh `MockContext` denying unused mocks.
+///
+/// Mocks of fixtures annotated with [`mock_fixture`](attr.mock_fixture.html) are set up in the context
+/// when they're listed in the `fixture` argument, later fixtures replace mocks of the earlier ones:
+///
+/// ```
+/// #[mocktopus::test(fixture(defaults, fixtures::slow_network))]
+/// fn add_test() { ... }
+/// ```
+#[proc_macro_attribute]
+pub fn mocktopus_test(attr: TokenStream, token_stream: TokenStream) -> TokenStream {
+    test_builder::build(
+        test_builder::TestKind::Sync,
+        attr.into(),
+        token_stream.into(),
+    )
+    .into()
+}
+
+/// Procedural macro, makes an async test function isolated from mocks of other tests,
+/// use it as `#[mocktopus::tokio_test]`.
+///
+/// Works like [`mocktopus::test`](attr.mocktopus_test.html) and replaces `#[tokio::test]`,
+/// which gets all the attribute arguments:
+///
+/// ```
+/// #[mocktopus::tokio_test(start_paused = true)]
+/// async fn fetch_test() {
+///     fetch.mock_safe(|_| MockResult::Return(Box::pin(async { 1 })));
+///
+///     assert_eq!(1, fetch("url").await);
+/// }
+/// ```
+/// Mocks are thread-local, so the test must run on a single thread, which is the `tokio::test` default.
+#[proc_macro_attribute]
+pub fn mocktopus_tokio_test(attr: TokenStream, token_stream: TokenStream) -> TokenStream {
+    test_builder::build(
+        test_builder::TestKind::Tokio,
+        attr.into(),
+        token_stream.into(),
+    )
+    .into()
+}
+
+/// Procedural macro, marks a function returning `MockSet` as a fixture,
+/// which can be used by tests annotated with [`mocktopus::test`](attr.mocktopus_test.html)
+///
+/// ```
+/// #[mock_fixture]
+/// fn defaults() -> MockSet {
+///     MockSet::new().mock(add, || |a, b| MockResult::Return(a * b))
+/// }
+///
+/// #[mocktopus::test(fixture(defaults))]
+/// fn add_test() {
+///     assert_eq!(6, add(2, 3));
+/// }
+/// ```
+/// The fixture is called once for every test using it, so each test gets fresh mocks.
+/// Mocks of fixtures, which were never called, are not reported as unused.
+#[proc_macro_attribute]
+pub fn mock_fixture(attr: TokenStream, token_stream: TokenStream) -> TokenStream {
+    test_builder::build_fixture(attr.into(), token_stream.into()).into()
+}
@@ -0,0 +1,144 @@
+use crate::type_tools;
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote, ToTokens};
+use syn::{
+    parse_quote, FnArg, GenericParam, ImplItemMethod, Item, ItemImpl, Lifetime, PatType, Receiver,
+    ReturnType, Type,
+};
+
+const BUILDER_METHOD_NAMES: [&str; 3] = ["new", "run", "into_context"];
+
+/// Builds `{Type}Mocks` builder with a typed setter for every mockable method of an inherent impl block.
+///
+/// Only non-generic impls of plain types are supported. Methods are skipped if they are async,
+/// take `impl Trait` argument or return a type with a lifetime.
+/// Setters, which would clash with the builder's own methods, are prefixed with `mock_`.
+pub fn build(item_impl: &ItemImpl, mockable_methods: &[&ImplItemMethod]) -> Vec<Item> {
+    let type_ident = match type_tools::get_impl_type_ident(item_impl) {
+        Some(type_ident) => type_ident,
+        None => return Vec::new(),
+    };
+    let self_ty = &*item_impl.self_ty;
+    let setters: Vec<_> = mockable_methods
+        .iter()
+        .filter_map(|item_method| build_setter(item_method, self_ty))
+        .collect();
+    if setters.is_empty() {
+        return Vec::new();
+    }
+    let mocks_ident = format_ident!("{}Mocks", type_ident);
+    let context = quote!(::mocktopus::mocking::MockContext<'mocktopus>);
+    vec![
+        // Builders are usually used in test modules, so they must be visible outside of their module
+        parse_quote! {
+            #[allow(dead_code)]
+            pub(crate) struct #mocks_ident<'mocktopus> {
+                context: #context,
+            }
+        },
+        parse_quote! {
+            #[allow(dead_code, private_interfaces, private_bounds)]
+            impl<'mocktopus> #mocks_ident<'mocktopus> {
+                pub(crate) fn new() -> Self {
+                    #mocks_ident {
+                        context: ::mocktopus::mocking::MockContext::new(),
+                    }
+                }
+
+                pub(crate) fn into_context(self) -> #context {
+                    self.context
+                }
+
+                pub(crate) fn run<T, F: FnOnce() -> T>(self, f: F) -> T {
+                    self.context.run(f)
+                }
+
+                #(#setters)*
+            }
+        },
+        parse_quote! {
+            impl<'mocktopus> From<#context> for #mocks_ident<'mocktopus> {
+                fn from(context: #context) -> Self {
+                    #mocks_ident { context }
+                }
+            }
+        },
+        parse_quote! {
+            impl<'mocktopus> From<#mocks_ident<'mocktopus>> for #context {
+                fn from(mocks: #mocks_ident<'mocktopus>) -> Self {
+                    mocks.context
+                }
+            }
+        },
+    ]
+}
+
+fn build_setter(item_method: &ImplItemMethod, self_ty: &Type) -> Option<TokenStream> {
+    let sig = &item_method.sig;
+    if sig.asyncness.is_some() {
+        return None;
+    }
+    let output = match sig.output {
+        ReturnType::Default => quote!(()),
+        ReturnType::Type(_, ref ty) if type_tools::is_plain_type(ty.to_token_stream()) => {
+            type_tools::replace_self_type(ty, self_ty).into_token_stream()
+        }
+        ReturnType::Type(..) => return None,
+    };
+    let mut lifetimes: Vec<Lifetime> = sig
+        .generics
+        .lifetimes()
+        .map(|lifetime_def| lifetime_def.lifetime.clone())
+        .collect();
+    let mut arg_types = Vec::new();
+    for fn_arg in &sig.inputs {
+        let arg_type = match fn_arg {
+            FnArg::Receiver(Receiver {
+                reference: Some((_, ref lifetime)),
+                ref mutability,
+                ..
+            }) => quote!(& #lifetime #mutability #self_ty),
+            FnArg::Receiver(_) => self_ty.to_token_stream(),
+            FnArg::Typed(PatType { ty, .. })
+                if type_tools::contains_ident(ty.to_token_stream(), "impl") =>
+            {
+                return None
+            }
+            FnArg::Typed(PatType { ty, .. }) => {
+                type_tools::replace_self_type(ty, self_ty).into_token_stream()
+            }
+        };
+        arg_types.push(type_tools::name_elided_lifetimes(arg_type, &mut lifetimes));
+    }
+    let ident = &sig.ident;
+    let setter_ident = match BUILDER_METHOD_NAMES.contains(&&*ident.to_string()) {
+        true => format_ident!("mock_{}", ident),
+        false => ident.clone(),
+    };
+    let type_params: Vec<_> = sig
+        .generics
+        .params
+        .iter()
+        .filter_map(|param| match param {
+            GenericParam::Type(type_param) => Some(type_param),
+            _ => None,
+        })
+        .collect();
+    let type_param_idents = type_params.iter().map(|type_param| &type_param.ident);
+    let where_predicates = sig
+        .generics
+        .where_clause
+        .iter()
+        .flat_map(|where_clause| &where_clause.predicates);
+    Some(quote! {
+        pub(crate) fn #setter_ident<#(#type_params,)* M>(self, mock: M) -> Self
+        where
+            #(#where_predicates,)*
+            M: for<#(#lifetimes),*> FnMut(#(#arg_types),*)
+                -> ::mocktopus::mocking::MockResult<(#(#arg_types,)*), #output> + 'mocktopus,
+        {
+            let context = self.context.mock_safe(#self_ty::#ident::<#(#type_param_idents),*>, mock);
+            Self { context }
+        }
+    })
+}
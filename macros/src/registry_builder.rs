@@ -0,0 +1,71 @@
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned, ToTokens};
+use syn::{Generics, Ident, Signature, Type, TypePath};
+
+/// Builds registration of a mockable function in the link-time registry of `mocktopus::mocking::registry`.
+///
+/// The function is described by its module, name of its `Self` type and trait if it has them,
+/// its signature before injection and its location in source code.
+/// Registration is a statement, which can be placed inside of the function body.
+pub fn build(
+    self_ty: Option<&Ident>,
+    trait_ident: Option<&Ident>,
+    fn_decl: &Signature,
+) -> TokenStream {
+    if !cfg!(feature = "registry") {
+        return TokenStream::new();
+    }
+    let self_ty = option_str(self_ty);
+    let trait_ident = option_str(trait_ident);
+    let name = fn_decl.ident.to_string();
+    let signature = fn_decl.to_token_stream().to_string();
+    let span = fn_decl.ident.span();
+    let file = quote_spanned!(span=> file!());
+    let line = quote_spanned!(span=> line!());
+    let column = quote_spanned!(span=> column!());
+    quote! {
+        const _: () = {
+            ::mocktopus::__private::inventory::submit! {
+                ::mocktopus::__private::RegisteredFn::new(
+                    module_path!(),
+                    #self_ty,
+                    #trait_ident,
+                    #name,
+                    #signature,
+                    #file,
+                    #line,
+                    #column,
+                )
+            }
+        };
+    }
+}
+
+/// Name of the implemented type, if it's a path to a concrete type
+pub fn get_self_ty_ident<'a>(self_ty: &'a Type, impl_generics: &Generics) -> Option<&'a Ident> {
+    let ident = match *self_ty {
+        Type::Path(TypePath {
+            qself: None,
+            ref path,
+        }) => &path.segments.last()?.ident,
+        Type::Reference(ref reference) => return get_self_ty_ident(&reference.elem, impl_generics),
+        _ => return None,
+    };
+    match impl_generics
+        .type_params()
+        .any(|param| param.ident == *ident)
+    {
+        true => None,
+        false => Some(ident),
+    }
+}
+
+fn option_str(ident: Option<&Ident>) -> TokenStream {
+    match ident {
+        Some(ident) => {
+            let ident = ident.to_string();
+            quote!(Some(#ident))
+        }
+        None => quote!(None),
+    }
+}
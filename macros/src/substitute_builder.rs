@@ -0,0 +1,144 @@
+use crate::type_tools;
+use proc_macro2::Span;
+use quote::{format_ident, quote, ToTokens};
+use syn::{
+    parse_quote, FnArg, Ident, ImplItemMethod, Item, ItemImpl, Pat, PatIdent, PatType, ReturnType,
+    Type,
+};
+
+const FAKE_TYPE_NAME: &str = "__MocktopusFake";
+
+/// Method of an impl block, which can be routed to a fake implementation
+struct SubstitutedMethod {
+    ident: Ident,
+    has_receiver: bool,
+    args: Vec<(Ident, Type)>,
+    output: ReturnType,
+}
+
+/// Builds `{Type}Api` trait and `Substitutable` impl for an inherent impl block.
+///
+/// Only non-generic impls of plain types are supported. Methods are skipped if they are generic,
+/// async or return a type with a lifetime, because a fake could not serve them.
+pub fn build(item_impl: &ItemImpl, mockable_methods: &[&ImplItemMethod]) -> Vec<Item> {
+    let type_ident = match type_tools::get_impl_type_ident(item_impl) {
+        Some(type_ident) => type_ident,
+        None => return Vec::new(),
+    };
+    let self_ty = &*item_impl.self_ty;
+    let methods: Vec<_> = mockable_methods
+        .iter()
+        .filter_map(|item_method| get_substituted_method(item_method, self_ty))
+        .collect();
+    if methods.is_empty() {
+        return Vec::new();
+    }
+    let api_ident = format_ident!("{}Api", type_ident);
+    vec![
+        build_api_trait(&api_ident, &methods),
+        build_substitutable_impl(&api_ident, self_ty, &methods),
+    ]
+}
+
+fn get_substituted_method(
+    item_method: &ImplItemMethod,
+    self_ty: &Type,
+) -> Option<SubstitutedMethod> {
+    let sig = &item_method.sig;
+    if sig.asyncness.is_some() || sig.generics.type_params().next().is_some() {
+        return None;
+    }
+    let mut has_receiver = false;
+    let mut args = Vec::new();
+    for (i, fn_arg) in sig.inputs.iter().enumerate() {
+        match fn_arg {
+            FnArg::Receiver(_) => has_receiver = true,
+            FnArg::Typed(PatType { pat, ty, .. }) => match **pat {
+                Pat::Ident(PatIdent { ref ident, .. }) if ident == "self" => has_receiver = true,
+                _ if type_tools::contains_ident(ty.to_token_stream(), "impl") => return None,
+                Pat::Ident(PatIdent { ref ident, .. }) => {
+                    args.push((ident.clone(), type_tools::replace_self_type(ty, self_ty)))
+                }
+                _ => args.push((
+                    format_ident!("arg{}", i),
+                    type_tools::replace_self_type(ty, self_ty),
+                )),
+            },
+        }
+    }
+    let output = match sig.output {
+        ReturnType::Default => ReturnType::Default,
+        ReturnType::Type(_, ref ty) if type_tools::is_plain_type(ty.to_token_stream()) => {
+            let ty = type_tools::replace_self_type(ty, self_ty);
+            parse_quote!(-> #ty)
+        }
+        ReturnType::Type(..) => return None,
+    };
+    Some(SubstitutedMethod {
+        ident: sig.ident.clone(),
+        has_receiver,
+        args,
+        output,
+    })
+}
+
+fn build_api_trait(api_ident: &Ident, methods: &[SubstitutedMethod]) -> Item {
+    let fns = methods.iter().map(|method| {
+        let SubstitutedMethod {
+            ident,
+            args,
+            output,
+            ..
+        } = method;
+        let (arg_names, arg_types): (Vec<_>, Vec<_>) = args.iter().cloned().unzip();
+        quote!(fn #ident(&mut self, #(#arg_names: #arg_types),*) #output;)
+    });
+    // Fakes are usually defined in test modules, so the trait must be visible outside of its module
+    parse_quote! {
+        #[allow(dead_code, private_interfaces)]
+        pub(crate) trait #api_ident {
+            #(#fns)*
+        }
+    }
+}
+
+fn build_substitutable_impl(
+    api_ident: &Ident,
+    self_ty: &Type,
+    methods: &[SubstitutedMethod],
+) -> Item {
+    let fake_type = Ident::new(FAKE_TYPE_NAME, Span::call_site());
+    let mocks = methods.iter().map(|method| {
+        let ident = &method.ident;
+        let arg_names: Vec<_> = method.args.iter().map(|(name, _)| name).collect();
+        let receiver = match method.has_receiver {
+            true => quote!(_,),
+            false => quote!(),
+        };
+        quote! {
+            let context = {
+                let __mocktopus_fake = fake.clone();
+                context.mock_safe(#self_ty::#ident, move |#receiver #(#arg_names),*| {
+                    ::mocktopus::mocking::MockResult::Return(__mocktopus_fake.with(
+                        |__mocktopus_fake| #api_ident::#ident(__mocktopus_fake, #(#arg_names),*),
+                    ))
+                })
+            };
+        }
+    });
+    parse_quote! {
+        #[allow(dead_code)]
+        impl<#fake_type: #api_ident> ::mocktopus::mocking::Substitutable<#fake_type> for #self_ty {
+            fn substitute<'mocktopus>(
+                context: ::mocktopus::mocking::MockContext<'mocktopus>,
+                fake: ::mocktopus::mocking::SharedFake<#fake_type>,
+            ) -> ::mocktopus::mocking::MockContext<'mocktopus>
+            where
+                #fake_type: 'mocktopus,
+            {
+                #(#mocks)*
+                context
+            }
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use crate::diagnostics::Diagnostics;
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote, quote_spanned};
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{ItemFn, Meta, NestedMeta, Path, ReturnType, Token};
+
+/// Kind of test function generated from an annotated function
+pub enum TestKind {
+    /// Plain `#[test]`, the annotated function must not be async
+    Sync,
+    /// `#[tokio::test]` getting the attribute arguments, the annotated function must be async
+    Tokio,
+}
+
+/// Builds a test function running the annotated function body isolated from mocks of other tests.
+///
+/// The body is wrapped in a call to `run_test` or `run_async_test` of `mocktopus::__private`
+/// getting mocks of fixtures listed in `fixture(...)` attribute argument.
+pub fn build(kind: TestKind, attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut diagnostics = Diagnostics::default();
+    let item_fn: ItemFn = match syn::parse2(item.clone()) {
+        Ok(item_fn) => item_fn,
+        Err(err) => {
+            diagnostics.error(
+                err.span(),
+                format!("Failed to make mocktopus test, failed to parse: {}", err),
+            );
+            let mut output = item;
+            output.extend(diagnostics.into_token_stream());
+            return output;
+        }
+    };
+    let (fixtures, other_args) = parse_args(attr, &mut diagnostics);
+    let is_async = item_fn.sig.asyncness.is_some();
+    let test_attr = match kind {
+        TestKind::Sync => {
+            if let Some(arg) = other_args.first() {
+                diagnostics.error(arg.span(), "test attribute only takes `fixture(...)`");
+            }
+            if is_async {
+                diagnostics.error(
+                    item_fn.sig.asyncness.span(),
+                    "async test must be annotated with `mocktopus::tokio_test`",
+                );
+            }
+            quote!(#[test])
+        }
+        TestKind::Tokio => {
+            if !is_async {
+                diagnostics.error(
+                    item_fn.sig.fn_token.span(),
+                    "tokio_test must be used on an async function",
+                );
+            }
+            if other_args.is_empty() {
+                quote!(#[::tokio::test])
+            } else {
+                quote!(#[::tokio::test(#(#other_args),*)])
+            }
+        }
+    };
+    if !item_fn.sig.inputs.is_empty() || !item_fn.sig.generics.params.is_empty() {
+        diagnostics.error(
+            Span::call_site(),
+            "test function can't take arguments or have generics",
+        );
+    }
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = item_fn;
+    let fixture_checks = fixtures.iter().map(|fixture| {
+        let marker = fixture_marker_path(fixture);
+        quote_spanned!(fixture.span()=> let _: () = #marker;)
+    });
+    let fixtures = quote! {
+        ::mocktopus::mocking::MockSet::new() #(.extend(#fixtures()))*
+    };
+    let body = if is_async {
+        quote!(::mocktopus::__private::run_async_test(#fixtures, async move #block).await)
+    } else {
+        quote!(::mocktopus::__private::run_test(#fixtures, move || #block))
+    };
+    let mut output = quote! {
+        #test_attr
+        #(#attrs)*
+        #vis #sig {
+            #(#fixture_checks)*
+            #body
+        }
+    };
+    output.extend(diagnostics.into_token_stream());
+    output
+}
+
+/// Splits attribute arguments into paths of fixtures and all the other arguments
+fn parse_args(attr: TokenStream, diagnostics: &mut Diagnostics) -> (Vec<Path>, Vec<NestedMeta>) {
+    let args = match Punctuated::<NestedMeta, Token![,]>::parse_terminated.parse2(attr) {
+        Ok(args) => args,
+        Err(err) => {
+            diagnostics.error(err.span(), format!("Failed to parse arguments: {}", err));
+            return (Vec::new(), Vec::new());
+        }
+    };
+    let mut fixtures = Vec::new();
+    let mut other_args = Vec::new();
+    for arg in args {
+        match arg {
+            NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("fixture") => {
+                for nested in list.nested {
+                    match nested {
+                        NestedMeta::Meta(Meta::Path(path)) => fixtures.push(path),
+                        other => diagnostics.error(other.span(), "fixture must be a function path"),
+                    }
+                }
+            }
+            other => other_args.push(other),
+        }
+    }
+    (fixtures, other_args)
+}
+
+/// Path of the marker constant generated next to a fixture function
+fn fixture_marker_path(fixture: &Path) -> Path {
+    let mut marker = fixture.clone();
+    if let Some(last) = marker.segments.last_mut() {
+        last.ident = format_ident!(
+            "__mocktopus_fixture_{}",
+            last.ident,
+            span = last.ident.span()
+        );
+    }
+    marker
+}
+
+/// Builds a fixture function returning `MockSet` together with a marker constant,
+/// which makes using functions not annotated as fixtures in tests a compilation error
+pub fn build_fixture(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut diagnostics = Diagnostics::default();
+    if !attr.is_empty() {
+        diagnostics.error(attr.span(), "mock_fixture attribute doesn't take arguments");
+    }
+    let item_fn: ItemFn = match syn::parse2(item.clone()) {
+        Ok(item_fn) => item_fn,
+        Err(err) => {
+            diagnostics.error(
+                err.span(),
+                format!("Failed to make mock fixture, failed to parse: {}", err),
+            );
+            let mut output = item;
+            output.extend(diagnostics.into_token_stream());
+            return output;
+        }
+    };
+    let sig = &item_fn.sig;
+    if sig.asyncness.is_some()
+        || !sig.inputs.is_empty()
+        || !sig.generics.params.is_empty()
+        || matches!(sig.output, ReturnType::Default)
+    {
+        diagnostics.error(
+            sig.ident.span(),
+            "mock fixture must be a non-async function without arguments or generics returning `MockSet`",
+        );
+    }
+    let vis = &item_fn.vis;
+    let marker = format_ident!("__mocktopus_fixture_{}", sig.ident, span = sig.ident.span());
+    let mut output = quote! {
+        #item_fn
+
+        #[doc(hidden)]
+        #[allow(non_upper_case_globals)]
+        #vis const #marker: () = ();
+    };
+    output.extend(diagnostics.into_token_stream());
+    output
+}
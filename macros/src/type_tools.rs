@@ -0,0 +1,105 @@
+use proc_macro2::{Group, Span, TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::{Ident, ItemImpl, Lifetime, Type, TypePath};
+
+/// Returns identifier of the type of a non-generic inherent impl block
+pub fn get_impl_type_ident(item_impl: &ItemImpl) -> Option<&Ident> {
+    if item_impl.trait_.is_some() || !item_impl.generics.params.is_empty() {
+        return None;
+    }
+    match *item_impl.self_ty {
+        Type::Path(TypePath {
+            qself: None,
+            ref path,
+        }) => path
+            .segments
+            .last()
+            .filter(|segment| segment.arguments.is_empty())
+            .map(|segment| &segment.ident),
+        _ => None,
+    }
+}
+
+/// Replaces all occurrences of `Self` in the type
+pub fn replace_self_type(ty: &Type, self_ty: &Type) -> Type {
+    let tokens = replace_self_type_in_token_stream(ty.to_token_stream(), self_ty);
+    syn::parse2(tokens).expect("Mocktopus internal error: type with replaced Self unparsable")
+}
+
+fn replace_self_type_in_token_stream(tokens: TokenStream, self_ty: &Type) -> TokenStream {
+    tokens
+        .into_iter()
+        .map(|token_tree| match token_tree {
+            TokenTree::Ident(ref ident) if ident == "Self" => self_ty.to_token_stream(),
+            TokenTree::Group(group) => {
+                let stream = replace_self_type_in_token_stream(group.stream(), self_ty);
+                let mut new_group = Group::new(group.delimiter(), stream);
+                new_group.set_span(group.span());
+                TokenTree::Group(new_group).into()
+            }
+            other => other.into(),
+        })
+        .collect()
+}
+
+/// Checks if type has no references, lifetimes and `impl Trait`s
+pub fn is_plain_type(tokens: TokenStream) -> bool {
+    tokens.into_iter().all(|token_tree| match token_tree {
+        TokenTree::Punct(punct) => punct.as_char() != '&' && punct.as_char() != '\'',
+        TokenTree::Ident(ident) => ident != "impl",
+        TokenTree::Group(group) => is_plain_type(group.stream()),
+        TokenTree::Literal(_) => true,
+    })
+}
+
+pub fn contains_ident(tokens: TokenStream, name: &str) -> bool {
+    tokens.into_iter().any(|token_tree| match token_tree {
+        TokenTree::Ident(ident) => ident == name,
+        TokenTree::Group(group) => contains_ident(group.stream(), name),
+        _ => false,
+    })
+}
+
+/// Gives names to all elided reference lifetimes, so they can be used in closure output
+pub fn name_elided_lifetimes(tokens: TokenStream, lifetimes: &mut Vec<Lifetime>) -> TokenStream {
+    let mut named = TokenStream::new();
+    let mut token_trees = tokens.into_iter().peekable();
+    while let Some(token_tree) = token_trees.next() {
+        match token_tree {
+            TokenTree::Punct(ref punct) if punct.as_char() == '&' => {
+                let is_elided = match token_trees.peek() {
+                    Some(TokenTree::Punct(next)) => next.as_char() != '\'',
+                    _ => true,
+                };
+                named.extend(Some(token_tree.clone()));
+                if is_elided {
+                    named.extend(new_lifetime(lifetimes).into_token_stream());
+                }
+            }
+            TokenTree::Punct(ref punct) if punct.as_char() == '\'' => match token_trees.next() {
+                Some(TokenTree::Ident(ref ident)) if ident == "_" => {
+                    named.extend(new_lifetime(lifetimes).into_token_stream())
+                }
+                Some(next) => named.extend(vec![token_tree.clone(), next]),
+                None => named.extend(Some(token_tree.clone())),
+            },
+            TokenTree::Group(group) => {
+                let stream = name_elided_lifetimes(group.stream(), lifetimes);
+                let mut new_group = Group::new(group.delimiter(), stream);
+                new_group.set_span(group.span());
+                named.extend(Some(TokenTree::Group(new_group)));
+            }
+            other => named.extend(Some(other)),
+        }
+    }
+    named
+}
+
+fn new_lifetime(lifetimes: &mut Vec<Lifetime>) -> Lifetime {
+    let lifetime = Lifetime::new(
+        &format!("'__mocktopus_{}", lifetimes.len()),
+        Span::call_site(),
+    );
+    lifetimes.push(lifetime.clone());
+    lifetime
+}
@@ -0,0 +1,147 @@
+//! Items used by code generated with `#[mockable]`, not a part of the public API
+
+use crate::mocking::{
+    active_mocks, clear_mocks, MockArgs, MockContext, MockResult, MockSet, Mockable,
+};
+#[cfg(feature = "registry")]
+pub use crate::registry::RegisteredFn;
+#[cfg(feature = "registry")]
+pub use inventory;
+use std::fmt::{self, Write};
+use std::future::Future;
+
+/// Outcome of looking for a mock of a called function
+pub enum Intercepted<T, O> {
+    /// Function body should run with given arguments and its result should be passed through
+    /// the closure if there is one
+    Continue(T, Option<Box<dyn FnOnce(O) -> O>>),
+    /// Function should return immediately with given value
+    Return(O),
+}
+
+/// The `!` type, which can't be named on stable Rust
+pub type Never = <fn() -> ! as FnOutput>::Output;
+
+/// Names output type of a function pointer
+pub trait FnOutput {
+    type Output;
+}
+
+impl<O> FnOutput for fn() -> O {
+    type Output = O;
+}
+
+/// Argument of a mockable function, which `DebugArg` or `SkipDebugArg` is picked for
+/// with autoref specialization, i.e. `(&ArgProbe(&arg)).debug_arg()`
+pub struct ArgProbe<'a, T>(pub &'a T);
+
+/// Shows arguments, which are `Debug`
+pub trait DebugArg<'a> {
+    fn debug_arg(&self) -> Option<&'a dyn fmt::Debug>;
+}
+
+impl<'a, T: fmt::Debug> DebugArg<'a> for ArgProbe<'a, T> {
+    fn debug_arg(&self) -> Option<&'a dyn fmt::Debug> {
+        Some(self.0)
+    }
+}
+
+/// Skips all other arguments, it's picked only if `DebugArg` isn't implemented
+pub trait SkipDebugArg<'a> {
+    fn debug_arg(&self) -> Option<&'a dyn fmt::Debug>;
+}
+
+impl<'a, T> SkipDebugArg<'a> for &ArgProbe<'a, T> {
+    fn debug_arg(&self) -> Option<&'a dyn fmt::Debug> {
+        None
+    }
+}
+
+/// Formats arguments for a message of a panicking mock, the skipped ones are shown as `_`.
+/// Returns `None` if all are skipped.
+pub fn format_debug_args(args: &[Option<&dyn fmt::Debug>]) -> Option<String> {
+    if args.iter().all(Option::is_none) {
+        return None;
+    }
+    let mut formatted = String::from("(");
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            formatted.push_str(", ");
+        }
+        match arg {
+            Some(arg) => write!(formatted, "{:?}", arg).expect("Formatting into String failed"),
+            None => formatted.push('_'),
+        }
+    }
+    formatted.push(')');
+    Some(formatted)
+}
+
+/// Called by every mockable function before running its body
+/// # Safety
+/// The caller must move arguments back into their bindings and cast the returned value
+/// to types differing only in lifetimes
+#[inline]
+pub unsafe fn intercept<F, T, O>(
+    function: &F,
+    args: T,
+    debug_args: &dyn Fn(&T) -> Option<String>,
+) -> Intercepted<T, O>
+where
+    F: Mockable<T, O>,
+    T: MockArgs,
+{
+    if !crate::mock_store::has_mocks() {
+        return Intercepted::Continue(args, None);
+    }
+    match function.call_mock(args, debug_args) {
+        MockResult::Continue(args) => Intercepted::Continue(args, None),
+        MockResult::ContinueThen(args, then) => Intercepted::Continue(args, Some(then)),
+        MockResult::Return(result) => Intercepted::Return(result),
+    }
+}
+
+/// Runs body of a test annotated with `#[mocktopus::test]` with mocks of its fixtures
+pub fn run_test<T>(fixtures: MockSet, test: impl FnOnce() -> T) -> T {
+    let _test_guard = TestGuard::start();
+    MockContext::new().with(&fixtures).deny_unused().run(test)
+}
+
+/// Runs body of a test annotated with `#[mocktopus::tokio_test]` with mocks of its fixtures
+pub async fn run_async_test<T>(fixtures: MockSet, test: impl Future<Output = T>) -> T {
+    let _test_guard = TestGuard::start();
+    let mock_layer_guard = MockContext::new().with(&fixtures).deny_unused().enter();
+    let result = test.await;
+    if let Err(error) = mock_layer_guard.exit() {
+        panic!("{}", error);
+    }
+    result
+}
+
+/// Clears mocks of the thread when the test ends, even if it panics
+struct TestGuard;
+
+impl TestGuard {
+    /// Clears mocks of the thread
+    ///
+    /// # Panics
+    /// Panics if there were mocks left by a previous test
+    fn start() -> Self {
+        let leftover = active_mocks();
+        clear_mocks();
+        if leftover
+            .layers()
+            .iter()
+            .any(|layer| !layer.fn_names().is_empty())
+        {
+            panic!("Mocks left by a previous test were cleared:\n{}", leftover);
+        }
+        TestGuard
+    }
+}
+
+impl Drop for TestGuard {
+    fn drop(&mut self) {
+        clear_mocks();
+    }
+}
@@ -0,0 +1,192 @@
+use std::fmt::{self, Debug, Display};
+use std::io::IsTerminal;
+
+/// Unchanged lines shown around each difference, longer runs of unchanged lines are elided
+const CONTEXT_LINES: usize = 3;
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+/// Line diff of pretty `Debug` output of an expected and an actual value
+///
+/// Pretty `Debug` output puts every struct field and collection item in a separate line,
+/// so the diff shows which fields differ even deep inside large arguments.
+/// It's shown by [assert_args_eq](fn.assert_args_eq.html)
+/// and can be used in failure messages of other checks done in mock closures:
+///
+/// ```
+/// #[test]
+/// fn save_test() {
+///     let expected = Request { id: 1, body: "body".to_string() };
+///     save.mock_safe(move |request| {
+///         assert!(request.id == expected.id, "{}", DebugDiff::new(&expected, &request));
+///         MockResult::Return(())
+///     });
+///
+///     send(2, "body");
+/// }
+/// ```
+/// When displayed, lines only in the expected value are prefixed with `-`,
+/// lines only in the actual value with `+`. They are colored if stderr is a terminal.
+pub struct DebugDiff {
+    lines: Vec<DiffLine>,
+    colored: bool,
+}
+
+enum DiffLine {
+    Same(String),
+    Expected(String),
+    Actual(String),
+}
+
+impl DebugDiff {
+    /// Compares pretty `Debug` output of the values
+    pub fn new<T: Debug + ?Sized>(expected: &T, actual: &T) -> Self {
+        let expected = format!("{:#?}", expected);
+        let actual = format!("{:#?}", actual);
+        let expected: Vec<&str> = expected.lines().collect();
+        let actual: Vec<&str> = actual.lines().collect();
+        DebugDiff {
+            lines: diff_lines(&expected, &actual),
+            colored: std::io::stderr().is_terminal(),
+        }
+    }
+
+    /// Overrides coloring the diff, which by default is enabled if stderr is a terminal
+    pub fn colored(mut self, colored: bool) -> Self {
+        self.colored = colored;
+        self
+    }
+
+    /// Returns true if the values have the same `Debug` output
+    pub fn is_same(&self) -> bool {
+        self.lines
+            .iter()
+            .all(|line| matches!(line, DiffLine::Same(_)))
+    }
+
+    fn is_near_difference(&self, idx: usize) -> bool {
+        let start = idx.saturating_sub(CONTEXT_LINES);
+        let end = (idx + CONTEXT_LINES + 1).min(self.lines.len());
+        self.lines[start..end]
+            .iter()
+            .any(|line| !matches!(line, DiffLine::Same(_)))
+    }
+}
+
+impl Display for DebugDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_same() {
+            return write!(f, "Debug output of expected and actual values is the same");
+        }
+        writeln!(f, "Difference (-expected +actual):")?;
+        let mut elided = false;
+        for (idx, line) in self.lines.iter().enumerate() {
+            let (sign, text, color) = match line {
+                DiffLine::Same(_) if !self.is_near_difference(idx) => {
+                    if !elided {
+                        writeln!(f, "  ...")?;
+                        elided = true;
+                    }
+                    continue;
+                }
+                DiffLine::Same(text) => (' ', text, None),
+                DiffLine::Expected(text) => ('-', text, Some(RED)),
+                DiffLine::Actual(text) => ('+', text, Some(GREEN)),
+            };
+            elided = false;
+            match color {
+                Some(color) if self.colored => writeln!(f, "{}{} {}{}", color, sign, text, RESET)?,
+                _ => writeln!(f, "{} {}", sign, text)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Debug for DebugDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+/// Asserts that arguments received by a mock closure are equal to the expected ones
+///
+/// On failure panics with a [DebugDiff](struct.DebugDiff.html) of the arguments:
+///
+/// ```
+/// #[test]
+/// fn save_test() {
+///     save.mock_safe(|request| {
+///         assert_args_eq(&Request { id: 1, body: "body".to_string() }, &request);
+///         MockResult::Return(())
+///     });
+///
+///     send(2, "body");
+/// }
+/// ```
+/// Many arguments can be compared at once as tuples, e.g. `assert_args_eq(&(1, "a"), &(a, b))`.
+#[track_caller]
+pub fn assert_args_eq<T: PartialEq + Debug + ?Sized>(expected: &T, actual: &T) {
+    if expected != actual {
+        panic!(
+            "Mock arguments differ from expected\n{}",
+            DebugDiff::new(expected, actual)
+        );
+    }
+}
+
+/// Longest common subsequence diff, common prefix and suffix are skipped
+/// to keep the quadratic part small for mostly equal values
+fn diff_lines(expected: &[&str], actual: &[&str]) -> Vec<DiffLine> {
+    let prefix = expected
+        .iter()
+        .zip(actual)
+        .take_while(|(expected, actual)| expected == actual)
+        .count();
+    let suffix = expected[prefix..]
+        .iter()
+        .rev()
+        .zip(actual[prefix..].iter().rev())
+        .take_while(|(expected, actual)| expected == actual)
+        .count();
+    let expected_mid = &expected[prefix..expected.len() - suffix];
+    let actual_mid = &actual[prefix..actual.len() - suffix];
+    // common[i][j] is length of LCS of expected_mid[i..] and actual_mid[j..]
+    let mut common = vec![vec![0_usize; actual_mid.len() + 1]; expected_mid.len() + 1];
+    for i in (0..expected_mid.len()).rev() {
+        for j in (0..actual_mid.len()).rev() {
+            common[i][j] = if expected_mid[i] == actual_mid[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let mut lines: Vec<DiffLine> = expected[..prefix]
+        .iter()
+        .map(|line| DiffLine::Same(line.to_string()))
+        .collect();
+    let (mut i, mut j) = (0, 0);
+    while i < expected_mid.len() || j < actual_mid.len() {
+        if i < expected_mid.len() && j < actual_mid.len() && expected_mid[i] == actual_mid[j] {
+            lines.push(DiffLine::Same(expected_mid[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if j == actual_mid.len()
+            || (i < expected_mid.len() && common[i + 1][j] >= common[i][j + 1])
+        {
+            lines.push(DiffLine::Expected(expected_mid[i].to_string()));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Actual(actual_mid[j].to_string()));
+            j += 1;
+        }
+    }
+    let suffix_lines = expected[expected.len() - suffix..]
+        .iter()
+        .map(|line| DiffLine::Same(line.to_string()));
+    lines.extend(suffix_lines);
+    lines
+}
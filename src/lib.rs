@@ -1,6 +1,8 @@
-#![feature(fn_traits, tuple_trait, unboxed_closures)]
+#![cfg_attr(feature = "nightly", feature(fn_traits, tuple_trait, unboxed_closures))]
+// Examples are not compiled, they show how to write tests in user's crate
+#![allow(clippy::test_attr_in_doctest)]
 
-//! Mocking framework for Rust (currently only nightly)
+//! Mocking framework for Rust (nightly, or stable with `default-features = false`)
 //!
 //! ```
 //! #[mockable]
@@ -46,6 +48,38 @@
 //! #[cfg(test)]
 //! extern crate mocktopus;
 //! ```
+//! ## Without nightly features
+//! By default Mocktopus uses unstable `fn_traits`, `tuple_trait` and `unboxed_closures` features.
+//! They can be turned off by disabling the default `nightly` feature, which makes it work on stable Rust:
+//!
+//! ```
+//! [dev-dependencies]
+//! mocktopus = { version = "0.7.0", default-features = false }
+//! ```
+//! Then only functions with up to 16 arguments can be mocked and types of arguments of mock closures
+//! are not inferred, so they must be annotated:
+//!
+//! ```
+//! #[test]
+//! fn add_test() {
+//!     add.mock_safe(|a: u32, b: u32| MockResult::Return(a * b));
+//!
+//!     assert_eq!(6, add(2, 3));
+//! }
+//! ```
+//! Closures passed to [mock_named](mocking/trait.Mockable.html#tymethod.mock_named) and
+//! to typed mock builders are not affected.
+//! ## Warnings about skipped items
+//! Items, which can't be made mockable, e.g. const functions or items generated by macros,
+//! are silently left intact. Enable `warn-skipped` feature to get a compiler warning for each of them.
+//! ## Registry of mockable functions
+//! With `registry` feature enabled every function made mockable is registered in a link-time registry
+//! listed by `mocking::registry`. In debug builds mocking a function, which isn't registered, prints a warning.
+//! ## Tests sharing threads
+//! Mocks are set up for the current thread, so when tests share threads, e.g. under custom harnesses,
+//! mocks of one test leak into the next ones. Replace `#[test]` with `#[mocktopus::test]`
+//! and `#[tokio::test]` with `#[mocktopus::tokio_test]` to clear the thread mocks before and after every test.
+//! The latter requires `tokio` dependency with `macros` feature.
 //! # Making functions mockable
 //! To make functions mockable they must be annotated with provided procedural macros.
 //! See [documentation](https://docs.rs/mocktopus_macros) for all their possibilities and rules.
@@ -149,6 +183,20 @@
 //!     assert_eq!(2, common_fn());
 //! }
 //! ```
+//! To mock only in a part of a test use `mock_scoped`. It mocks only while the closure passed
+//! as the second argument runs. The mock closure may borrow local values, because it's removed before they're gone:
+//!
+//! ```
+//! #[test]
+//! fn common_fn_test_3() {
+//!     let mocked = 3;
+//!     common_fn.mock_scoped(|| MockResult::Return(mocked), || {
+//!         assert_eq!(3, common_fn());
+//!     });
+//!
+//!     assert_eq!(0, common_fn());
+//! }
+//! ```
 //!
 //! ## Mock closure
 //! `mock_safe` has single argument: a closure, which takes same input as mocked function and returns a `MockResult`.
@@ -203,6 +251,17 @@
 //!     assert_eq!(3, my_function_3(1, 1));
 //! }
 //! ```
+//! If the closure returns `MockResult::ContinueThen`, the mocked function runs normally
+//! and then its result is passed through the provided closure:
+//!
+//! ```
+//! #[test]
+//! fn my_function_3_continue_then_test() {
+//!     my_function_3.mock_safe(|x, y| MockResult::continue_then((x, y), |result: u32| result * 10));
+//!
+//!     assert_eq!(20, my_function_3(1, 1));
+//! }
+//! ```
 //!
 //! ## Mocking generics
 //! When mocking generic functions, all its generics must be defined and only this variant will be affected:
@@ -277,6 +336,71 @@
 //! }
 //! ```
 //!
+//! ## Substituting whole impl blocks
+//! Stateful collaborators are easier to replace with a fake than to mock method by method.
+//! For a non-generic inherent impl block annotated as `mockable(substitute)` a trait named after
+//! the type with `Api` suffix is generated. A fake implementing it can serve all the methods at once:
+//!
+//! ```
+//! struct Repository;
+//!
+//! #[cfg_attr(test, mockable(substitute))]
+//! impl Repository {
+//!     fn insert(&mut self, item: String) { ... }
+//!     fn count(&self) -> usize { ... }
+//! }
+//!
+//! #[derive(Default)]
+//! struct FakeRepository(Vec<String>);
+//!
+//! impl RepositoryApi for FakeRepository {
+//!     fn insert(&mut self, item: String) {
+//!         self.0.push(item)
+//!     }
+//!
+//!     fn count(&mut self) -> usize {
+//!         self.0.len()
+//!     }
+//! }
+//!
+//! #[test]
+//! fn repository_test() {
+//!     MockContext::new()
+//!         .substitute::<Repository, _>(FakeRepository::default())
+//!         .run(|| {
+//!             let mut repository = Repository;
+//!             repository.insert("item".to_string());
+//!             assert_eq!(1, repository.count());
+//!         });
+//! }
+//! ```
+//!
+//! ## Typed mock builders
+//! For a non-generic inherent impl block annotated as `mockable(mocks)` a builder named after the type
+//! with `Mocks` suffix is generated. It has a setter for every method, which accepts the same closures
+//! as [MockContext](mocking/struct.MockContext.html), so a typo or a renamed method breaks the test
+//! at compile time. The builder is `pub(crate)`, so it can be used in test modules:
+//!
+//! ```
+//! #[cfg_attr(test, mockable(mocks))]
+//! impl Connection {
+//!     fn connect(address: &str) -> Connection { ... }
+//!     fn send(&self, buffer: &[u8]) -> usize { ... }
+//! }
+//!
+//! #[test]
+//! fn connection_test() {
+//!     ConnectionMocks::new()
+//!         .connect(|_| MockResult::Continue(("localhost",)))
+//!         .send(|_, buffer| MockResult::Return(buffer.len()))
+//!         .run(|| {
+//!             assert_eq!(3, Connection::connect("remote").send(&[1, 2, 3]));
+//!         });
+//! }
+//! ```
+//! Setters clashing with the builder's own methods `new`, `run` and `into_context` are prefixed
+//! with `mock_`. Builders convert to and from `MockContext` with `From` and `Into`.
+//!
 //! # Mocking tricks
 //! ## Returning reference to value created inside mock
 //!
@@ -299,6 +423,23 @@
 //! process termination, so it's viable solution only for use in tests and only if structure doesn't block a lot of
 //! resources like huge amounts of memory, open file handlers, sockets, etc.
 //!
+//! Values can be freed after the test with [MockArena](mocking_utils/struct.MockArena.html). It must be created
+//! before the mocks using it and it hands out references living as long as itself:
+//!
+//! ```
+//! #[test]
+//! fn my_fn_arena_test() {
+//!     let arena = MockArena::new();
+//!     MockContext::new()
+//!         .mock_safe(my_fn, |_| MockResult::Return(arena.alloc("mocked".to_string())))
+//!         .run(|| {
+//!             assert_eq!("mocked", my_fn(&"not mocked"));
+//!         });
+//! }
+//! ```
+//! Mutable references can be returned with `alloc_mut`. If a mutable reference to the same value must be
+//! handed out multiple times, use `slot`, which checks borrowing rules at runtime.
+//!
 //! ## Returning value created outside of mock
 //!
 //! ```
@@ -309,15 +450,16 @@
 //!
 //! #[test]
 //! fn my_fn_test() {
-//!     mock = Some("mocked".to_string());
-//!     my_fn.mock_safe(move || MockResult::Return(mock.unwrap()));
+//!     let mock = "mocked".to_string();
+//!     my_fn.mock_once(move || MockResult::Return(mock));
 //!
 //!     assert_eq!("mocked", my_fn());
-//!     // assert_eq!("mocked", my_fn()); // WILL PANIC!
+//!     assert_eq!("not mocked", my_fn());
 //! }
 //! ```
-//! This makes function return predefined value on first call and panic on second one. It could return
-//! `MockResult::Continue` instead of panicking to mock only first call.
+//! This makes function return predefined value on first call. The mock closure is `FnOnce`, so it can move
+//! captured values out. After the first call the mock expires and the function runs normally.
+//! Use `mock_times` to serve a fixed number of calls with an `FnMut` closure.
 //!
 //! Returned values can be stored in a vector if mock should return different value on different calls:
 //!
@@ -351,4 +493,13 @@ pub mod macros {
     pub use mocktopus_macros::*;
 }
 
+pub use mocktopus_macros::{mocktopus_test as test, mocktopus_tokio_test as tokio_test};
+
+#[doc(hidden)]
+pub mod __private;
+
+mod debug_diff;
+mod mock_fn;
 mod mock_store;
+#[cfg(feature = "registry")]
+mod registry;
@@ -0,0 +1,232 @@
+use crate::mocking::{CallInfo, MockResult};
+#[cfg(feature = "nightly")]
+use std::marker::Tuple;
+
+/// Tuple of arguments of a mockable function
+///
+/// Implemented for all types. With `nightly` feature enabled it's implemented only for tuples.
+#[cfg(feature = "nightly")]
+pub trait MockArgs: Tuple {}
+
+#[cfg(feature = "nightly")]
+impl<T: Tuple> MockArgs for T {}
+
+/// Tuple of arguments of a mockable function
+///
+/// Implemented for all types. With `nightly` feature enabled it's implemented only for tuples.
+#[cfg(not(feature = "nightly"))]
+pub trait MockArgs {}
+
+#[cfg(not(feature = "nightly"))]
+impl<T> MockArgs for T {}
+
+/// Mock closure, which can be called many times
+///
+/// Implemented for closures taking function arguments and returning [MockResult](enum.MockResult.html).
+/// With `nightly` feature enabled it's implemented for closures taking any number of arguments.
+/// Otherwise it's implemented for closures taking up to 16 arguments, which types can't be inferred,
+/// so they must be annotated, e.g. `|a: u32, b: &str| MockResult::Continue((a, b))`.
+#[cfg(feature = "nightly")]
+pub trait MockFn<T: MockArgs, O>: FnMut<T, Output = MockResult<T, O>> {
+    /// Calls the closure
+    fn call_mock_fn(&mut self, args: T) -> MockResult<T, O>;
+}
+
+#[cfg(feature = "nightly")]
+impl<T: MockArgs, O, M: FnMut<T, Output = MockResult<T, O>>> MockFn<T, O> for M {
+    fn call_mock_fn(&mut self, args: T) -> MockResult<T, O> {
+        self.call_mut(args)
+    }
+}
+
+/// Mock closure, which can be called at most once
+///
+/// Implemented for the same closures as [MockFn](trait.MockFn.html), which can be called only once.
+#[cfg(feature = "nightly")]
+pub trait MockFnOnce<T: MockArgs, O>: FnOnce<T, Output = MockResult<T, O>> {
+    /// Calls the closure
+    fn call_mock_fn_once(self, args: T) -> MockResult<T, O>;
+}
+
+#[cfg(feature = "nightly")]
+impl<T: MockArgs, O, M: FnOnce<T, Output = MockResult<T, O>>> MockFnOnce<T, O> for M {
+    fn call_mock_fn_once(self, args: T) -> MockResult<T, O> {
+        self.call_once(args)
+    }
+}
+
+/// Mock closure, which receives [CallInfo](struct.CallInfo.html) before the function arguments
+///
+/// Implemented for closures taking `&CallInfo` and up to 16 function arguments
+/// and returning [MockResult](enum.MockResult.html).
+/// Without `nightly` feature the types of arguments can't be inferred, so they must be annotated,
+/// e.g. `|info: &CallInfo, a: u32| MockResult::Continue((a + info.call_index() as u32,))`.
+#[cfg(feature = "nightly")]
+pub trait MockInfoFn<T: MockArgs + for<'i> PrependInfo<'i>, O>:
+    for<'i> FnMut<<T as PrependInfo<'i>>::Args, Output = MockResult<T, O>>
+{
+    /// Calls the closure
+    fn call_mock_info_fn(&mut self, info: &CallInfo, args: T) -> MockResult<T, O>;
+}
+
+#[cfg(feature = "nightly")]
+impl<T, O, M> MockInfoFn<T, O> for M
+where
+    T: MockArgs + for<'i> PrependInfo<'i>,
+    M: for<'i> FnMut<<T as PrependInfo<'i>>::Args, Output = MockResult<T, O>>,
+{
+    fn call_mock_info_fn(&mut self, info: &CallInfo, args: T) -> MockResult<T, O> {
+        self.call_mut(args.prepend_info(info))
+    }
+}
+
+/// Mock closure, which can be called many times
+///
+/// Implemented for closures taking function arguments and returning [MockResult](enum.MockResult.html).
+/// With `nightly` feature enabled it's implemented for closures taking any number of arguments.
+/// Otherwise it's implemented for closures taking up to 16 arguments, which types can't be inferred,
+/// so they must be annotated, e.g. `|a: u32, b: &str| MockResult::Continue((a, b))`.
+#[cfg(not(feature = "nightly"))]
+pub trait MockFn<T: MockArgs, O> {
+    /// Calls the closure
+    fn call_mock_fn(&mut self, args: T) -> MockResult<T, O>;
+}
+
+/// Mock closure, which can be called at most once
+///
+/// Implemented for the same closures as [MockFn](trait.MockFn.html), which can be called only once.
+#[cfg(not(feature = "nightly"))]
+pub trait MockFnOnce<T: MockArgs, O> {
+    /// Calls the closure
+    fn call_mock_fn_once(self, args: T) -> MockResult<T, O>;
+}
+
+/// Mock closure, which receives [CallInfo](struct.CallInfo.html) before the function arguments
+///
+/// Implemented for closures taking `&CallInfo` and up to 16 function arguments
+/// and returning [MockResult](enum.MockResult.html).
+/// Without `nightly` feature the types of arguments can't be inferred, so they must be annotated,
+/// e.g. `|info: &CallInfo, a: u32| MockResult::Continue((a + info.call_index() as u32,))`.
+#[cfg(not(feature = "nightly"))]
+pub trait MockInfoFn<T: MockArgs + for<'i> PrependInfo<'i>, O> {
+    /// Calls the closure
+    fn call_mock_info_fn(&mut self, info: &CallInfo, args: T) -> MockResult<T, O>;
+}
+
+/// Tuple of function arguments, which can be preceded by [CallInfo](struct.CallInfo.html)
+///
+/// Implemented for tuples of up to 16 elements.
+pub trait PrependInfo<'i> {
+    /// Tuple of `&CallInfo` followed by the arguments
+    type Args: MockArgs;
+
+    /// Puts `info` before the arguments
+    fn prepend_info(self, info: &'i CallInfo) -> Self::Args;
+}
+
+/// Mock stored in `MockStore`, implemented by mock closures wrapped in `FnMock` and mock adapters
+pub trait DynMock<T, O> {
+    /// Calls the mock, `info` builds information about the call for mocks, which take it
+    fn call_mock(&mut self, info: &dyn Fn() -> CallInfo, args: T) -> MockResult<T, O>;
+}
+
+/// Wraps `MockFn`, so it can be stored as `DynMock`
+pub struct FnMock<M>(pub M);
+
+impl<T: MockArgs, O, M: MockFn<T, O>> DynMock<T, O> for FnMock<M> {
+    fn call_mock(&mut self, _: &dyn Fn() -> CallInfo, args: T) -> MockResult<T, O> {
+        self.0.call_mock_fn(args)
+    }
+}
+
+/// Wraps `MockInfoFn`, so it can be stored as `DynMock`
+pub struct InfoFnMock<M>(pub M);
+
+impl<T: MockArgs + for<'i> PrependInfo<'i>, O, M: MockInfoFn<T, O>> DynMock<T, O>
+    for InfoFnMock<M>
+{
+    fn call_mock(&mut self, info: &dyn Fn() -> CallInfo, args: T) -> MockResult<T, O> {
+        self.0.call_mock_info_fn(&info(), args)
+    }
+}
+
+macro_rules! impl_prepend_info {
+    ($($arg:ident),*) => {
+        impl<'i, $($arg),*> PrependInfo<'i> for ($($arg,)*) {
+            type Args = (&'i CallInfo, $($arg),*);
+
+            #[allow(non_snake_case)]
+            fn prepend_info(self, info: &'i CallInfo) -> Self::Args {
+                let ($($arg,)*) = self;
+                (info, $($arg),*)
+            }
+        }
+    };
+}
+
+#[cfg(not(feature = "nightly"))]
+macro_rules! impl_mock_fns {
+    ($($arg:ident),*) => {
+        impl<$($arg,)* O, M: FnMut($($arg),*) -> MockResult<($($arg,)*), O>> MockFn<($($arg,)*), O>
+            for M
+        {
+            #[allow(non_snake_case)]
+            fn call_mock_fn(&mut self, ($($arg,)*): ($($arg,)*)) -> MockResult<($($arg,)*), O> {
+                self($($arg),*)
+            }
+        }
+
+        impl<$($arg,)* O, M: FnOnce($($arg),*) -> MockResult<($($arg,)*), O>>
+            MockFnOnce<($($arg,)*), O> for M
+        {
+            #[allow(non_snake_case)]
+            fn call_mock_fn_once(self, ($($arg,)*): ($($arg,)*)) -> MockResult<($($arg,)*), O> {
+                self($($arg),*)
+            }
+        }
+
+        impl<$($arg,)* O, M: FnMut(&CallInfo, $($arg),*) -> MockResult<($($arg,)*), O>>
+            MockInfoFn<($($arg,)*), O> for M
+        {
+            #[allow(non_snake_case)]
+            fn call_mock_info_fn(
+                &mut self,
+                info: &CallInfo,
+                ($($arg,)*): ($($arg,)*),
+            ) -> MockResult<($($arg,)*), O> {
+                self(info, $($arg),*)
+            }
+        }
+    };
+}
+
+/// Invokes macro for arguments lists of all arities from 0 to 16
+macro_rules! for_all_arities {
+    ($macro:ident) => {
+        $macro!();
+        $macro!(A0);
+        $macro!(A0, A1);
+        $macro!(A0, A1, A2);
+        $macro!(A0, A1, A2, A3);
+        $macro!(A0, A1, A2, A3, A4);
+        $macro!(A0, A1, A2, A3, A4, A5);
+        $macro!(A0, A1, A2, A3, A4, A5, A6);
+        $macro!(A0, A1, A2, A3, A4, A5, A6, A7);
+        $macro!(A0, A1, A2, A3, A4, A5, A6, A7, A8);
+        $macro!(A0, A1, A2, A3, A4, A5, A6, A7, A8, A9);
+        $macro!(A0, A1, A2, A3, A4, A5, A6, A7, A8, A9, A10);
+        $macro!(A0, A1, A2, A3, A4, A5, A6, A7, A8, A9, A10, A11);
+        $macro!(A0, A1, A2, A3, A4, A5, A6, A7, A8, A9, A10, A11, A12);
+        $macro!(A0, A1, A2, A3, A4, A5, A6, A7, A8, A9, A10, A11, A12, A13);
+        $macro!(A0, A1, A2, A3, A4, A5, A6, A7, A8, A9, A10, A11, A12, A13, A14);
+        $macro!(A0, A1, A2, A3, A4, A5, A6, A7, A8, A9, A10, A11, A12, A13, A14, A15);
+    };
+}
+
+#[cfg(not(feature = "nightly"))]
+for_all_arities!(impl_mock_fns);
+
+for_all_arities!(impl_prepend_info);
+
+#[cfg(not(feature = "nightly"))]
+pub(crate) use for_all_arities;
@@ -1,67 +1,360 @@
-use crate::mocking::MockResult;
-use std::{any::TypeId, marker::Tuple};
-use std::cell::RefCell;
+use crate::mock_fn::DynMock;
+use crate::mocking::{ActiveMocks, CallInfo, MockLayerInfo, MockResult};
+use std::any::{Any, TypeId};
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hasher};
 use std::mem::transmute;
+use std::panic::{self, AssertUnwindSafe};
 use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
+/// Number of mocks alive in all threads
+static MOCK_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns false if there are no mocks in any thread, so calls to mockable functions can skip the store
+pub fn has_mocks() -> bool {
+    MOCK_COUNT.load(Ordering::Relaxed) != 0
+}
+
+/// Mocks of all functions, each one having a stack of mocks from all layers.
+/// The thread layer has index 0, contexts and named layers get subsequent indices in order of pushing.
+#[derive(Default)]
 pub struct MockStore {
-    layers: RefCell<Vec<MockLayer>>,
+    mocks: RefCell<IdMap<MockStack>>,
+    /// Layers pushed on top of the thread layer, from the outermost one
+    layers: RefCell<Vec<StoredLayer>>,
+    next_layer_key: Cell<u64>,
+    /// IDs of functions, which calls are being handled by mocks, from the outermost one
+    running: RefCell<Vec<TypeId>>,
+    #[cfg(feature = "debug-checks")]
+    scopes: RefCell<Vec<u64>>,
+    #[cfg(feature = "debug-checks")]
+    next_generation: Cell<u64>,
 }
 
+const THREAD_LAYER: usize = 0;
+
 impl MockStore {
     pub fn clear(&self) {
-        for layer in self.layers.borrow_mut().iter_mut() {
-            layer.clear()
-        }
+        self.mocks.borrow_mut().clear()
     }
 
     pub fn clear_id(&self, id: TypeId) {
-        for layer in self.layers.borrow_mut().iter_mut() {
-            layer.clear_id(id)
+        self.mocks.borrow_mut().remove(&id);
+    }
+
+    /// Returns key of the layer, which must be used to remove it
+    /// Mocks of the layer must be valid until it's removed
+    pub unsafe fn add_layer(&self, layer: MockLayer, name: Option<&'static str>) -> u64 {
+        let mut layers = self.layers.borrow_mut();
+        let layer_idx = layers.len() + 1;
+        let mut mocks = self.mocks.borrow_mut();
+        let ids = layer
+            .mocks
+            .into_iter()
+            .map(|(id, context_mock)| {
+                let fn_name = context_mock.fn_name;
+                let layered = LayeredMock {
+                    layer: layer_idx,
+                    mock: context_mock.mock,
+                };
+                mocks
+                    .entry(id)
+                    .or_insert_with(|| MockStack::new(fn_name))
+                    .mocks
+                    .push(layered);
+                (id, context_mock.report_unused)
+            })
+            .collect();
+        let key = self.next_layer_key.get();
+        self.next_layer_key.set(key + 1);
+        layers.push(StoredLayer { key, name, ids });
+        key
+    }
+
+    /// Layers may be removed in any order, layers above the removed one get their indices decremented.
+    /// Returns sorted names of functions, which mocks in the removed layer were never called
+    pub fn remove_layer(&self, key: u64) -> Vec<&'static str> {
+        let mut layers = self.layers.borrow_mut();
+        let position = layers
+            .iter()
+            .position(|layer| layer.key == key)
+            .expect("Mock layer already removed");
+        let layer_idx = position + 1;
+        let ids = layers.remove(position).ids;
+        let mut mocks = self.mocks.borrow_mut();
+        let mut unused = Vec::new();
+        for (id, report_unused) in ids {
+            if let Some(stack) = mocks.get(&id) {
+                let is_unused = stack
+                    .mocks
+                    .iter()
+                    .any(|stored| stored.layer == layer_idx && stored.mock.calls() == 0);
+                if report_unused && is_unused {
+                    unused.push(stack.fn_name);
+                }
+            }
+            retain(&mut mocks, id, |stored| stored.layer != layer_idx)
         }
+        if layer_idx <= layers.len() {
+            for stored in mocks.values_mut().flat_map(|stack| &mut stack.mocks) {
+                if stored.layer > layer_idx {
+                    stored.layer -= 1;
+                }
+            }
+        }
+        unused.sort_unstable();
+        unused
     }
 
-    /// Layer will be in use as long as MockLayerGuard is alive
-    /// MockLayerGuards must always be dropped and always in reverse order of their creation
-    pub unsafe fn add_layer(&self, layer: MockLayer) {
-        self.layers.borrow_mut().push(layer)
+    /// Key of the innermost layer with the given name
+    pub fn find_layer(&self, name: &str) -> Option<u64> {
+        let layers = self.layers.borrow();
+        let layer = layers.iter().rev().find(|layer| layer.name == Some(name))?;
+        Some(layer.key)
     }
 
-    pub unsafe fn remove_layer(&self) {
-        self.layers.borrow_mut().pop();
+    /// Replaces mock of the function in the layer, which must not be removed before the mock is
+    pub unsafe fn add_to_layer<I, O>(
+        &self,
+        key: u64,
+        id: TypeId,
+        fn_name: &'static str,
+        mock: Box<dyn DynMock<I, O> + 'static>,
+        times: Option<usize>,
+    ) {
+        let layer_idx = self.record_in_layer(key, id);
+        let layered = LayeredMock {
+            layer: layer_idx,
+            mock: ErasedStoredMock::new(mock, times),
+        };
+        let mut mocks = self.mocks.borrow_mut();
+        let stack = &mut mocks
+            .entry(id)
+            .or_insert_with(|| MockStack::new(fn_name))
+            .mocks;
+        stack.retain(|stored| stored.layer != layer_idx);
+        let position = stack
+            .iter()
+            .position(|stored| stored.layer > layer_idx)
+            .unwrap_or(stack.len());
+        stack.insert(position, layered);
     }
 
-    pub unsafe fn add_to_thread_layer<I: Tuple, O>(
+    /// Replaces mock of the function in the innermost layer, which is the thread layer if there are no others
+    pub unsafe fn add_to_innermost_layer<I, O>(
         &self,
         id: TypeId,
-        mock: Box<dyn FnMut<I, Output = MockResult<I, O>> + 'static>,
+        fn_name: &'static str,
+        mock: Box<dyn DynMock<I, O> + 'static>,
+        times: Option<usize>,
     ) {
-        self.layers
-            .borrow_mut()
-            .first_mut()
-            .expect("Thread mock level missing")
-            .add(id, mock);
-    }
-
-    pub unsafe fn call<I: Tuple, O>(&self, id: TypeId, mut input: I) -> MockResult<I, O> {
-        // Do not hold RefCell borrow while calling mock, it can try to modify mocks
-        let layer_count = self.layers.borrow().len();
-        for layer_idx in (0..layer_count).rev() {
-            let mock_opt = self
-                .layers
-                .borrow()
-                .get(layer_idx)
-                .expect("Mock layer removed while iterating")
-                .get(id);
-            if let Some(mock) = mock_opt {
-                match mock.call(input) {
-                    MockLayerResult::Handled(result) => return result,
-                    MockLayerResult::Unhandled(new_input) => input = new_input,
-                }
+        let key = self.layers.borrow().last().map(|layer| layer.key);
+        match key {
+            Some(key) => self.add_to_layer(key, id, fn_name, mock, times),
+            None => self.add_to_thread_layer(id, fn_name, mock, times),
+        }
+    }
+
+    /// Adds function ID to the layer's IDs and returns index of the layer
+    fn record_in_layer(&self, key: u64, id: TypeId) -> usize {
+        let mut layers = self.layers.borrow_mut();
+        let position = layers
+            .iter()
+            .position(|layer| layer.key == key)
+            .expect("Mock layer already removed");
+        let layer = &mut layers[position];
+        if !layer.ids.iter().any(|(layer_id, _)| *layer_id == id) {
+            // Only contexts report their unused mocks
+            layer.ids.push((id, layer.name.is_none()));
+        }
+        position + 1
+    }
+
+    /// Removes all mocks of the layer, but keeps the layer
+    pub fn clear_layer(&self, key: u64) {
+        let mut layers = self.layers.borrow_mut();
+        let position = layers
+            .iter()
+            .position(|layer| layer.key == key)
+            .expect("Mock layer already removed");
+        let layer_idx = position + 1;
+        let mut mocks = self.mocks.borrow_mut();
+        for (id, _) in layers[position].ids.drain(..) {
+            retain(&mut mocks, id, |stored| stored.layer != layer_idx)
+        }
+    }
+
+    pub unsafe fn add_to_thread_layer<I, O>(
+        &self,
+        id: TypeId,
+        fn_name: &'static str,
+        mock: Box<dyn DynMock<I, O> + 'static>,
+        times: Option<usize>,
+    ) {
+        let layered = LayeredMock {
+            layer: THREAD_LAYER,
+            mock: ErasedStoredMock::new(mock, times),
+        };
+        let mut mocks = self.mocks.borrow_mut();
+        let stack = &mut mocks
+            .entry(id)
+            .or_insert_with(|| MockStack::new(fn_name))
+            .mocks;
+        stack.retain(|stored| stored.layer != THREAD_LAYER);
+        stack.insert(0, layered);
+    }
+
+    /// Mock is pushed on top of the mocks of the same function in the innermost layer and stays there
+    /// until it's removed with the returned handle, cleared or its layer is removed
+    pub unsafe fn push_to_innermost_layer<I, O>(
+        &self,
+        id: TypeId,
+        fn_name: &'static str,
+        mock: Box<dyn DynMock<I, O> + 'static>,
+    ) -> MockHandle {
+        let key = self.layers.borrow().last().map(|layer| layer.key);
+        let layer_idx = match key {
+            Some(key) => self.record_in_layer(key, id),
+            None => THREAD_LAYER,
+        };
+        let mock = ErasedStoredMock::new(mock, None);
+        let layered = LayeredMock {
+            layer: layer_idx,
+            mock: mock.clone(),
+        };
+        let mut mocks = self.mocks.borrow_mut();
+        let stack = &mut mocks
+            .entry(id)
+            .or_insert_with(|| MockStack::new(fn_name))
+            .mocks;
+        let position = stack
+            .iter()
+            .position(|stored| stored.layer > layer_idx)
+            .unwrap_or(stack.len());
+        stack.insert(position, layered);
+        MockHandle { id, mock }
+    }
+
+    pub fn remove_pushed(&self, handle: &MockHandle) {
+        retain(&mut self.mocks.borrow_mut(), handle.id, |stored| {
+            !stored.mock.is_same(&handle.mock)
+        })
+    }
+
+    pub fn is_mocked(&self, id: TypeId) -> bool {
+        self.mocks.borrow().contains_key(&id)
+    }
+
+    /// Names of mocked functions in every layer, including the empty ones
+    pub fn active_mocks(&self) -> ActiveMocks {
+        let names: Vec<_> = std::iter::once(None)
+            .chain(self.layers.borrow().iter().map(|layer| layer.name))
+            .collect();
+        let mut layers = vec![Vec::new(); names.len()];
+        for stack in self.mocks.borrow().values() {
+            for stored in &stack.mocks {
+                layers[stored.layer].push(stack.fn_name);
             }
         }
-        MockResult::Continue(input)
+        let layers = layers
+            .into_iter()
+            .zip(names)
+            .enumerate()
+            .map(|(depth, (mut fn_names, name))| {
+                fn_names.sort_unstable();
+                fn_names.dedup();
+                MockLayerInfo::new(depth, name, fn_names)
+            })
+            .collect();
+        ActiveMocks::new(layers)
+    }
+
+    pub unsafe fn call<I, O>(
+        &self,
+        id: TypeId,
+        mut input: I,
+        debug_args: &dyn Fn(&I) -> Option<String>,
+    ) -> MockResult<I, O> {
+        let reentrant = self.running.borrow().contains(&id);
+        let call_index = match self.mocks.borrow().get(&id) {
+            Some(stack) => stack.count_call(),
+            None => return MockResult::Continue(input),
+        };
+        // Only the top mock of each layer is tried, unhandled calls fall through to the layer below
+        let mut below_layer = usize::MAX;
+        loop {
+            // Do not hold RefCell borrow while calling mock, it can try to modify mocks
+            let found = self.mocks.borrow().get(&id).and_then(|stack| {
+                let layered = stack
+                    .mocks
+                    .iter()
+                    .rev()
+                    .find(|stored| stored.layer < below_layer)?;
+                Some((stack.fn_name, layered.clone()))
+            });
+            let (fn_name, layered) = match found {
+                Some(found) => found,
+                None => return MockResult::Continue(input),
+            };
+            // Built only for mocks taking it, capturing the thread isn't free
+            let info = || CallInfo::new(call_index, fn_name, layered.layer, reentrant);
+            // Formatted before the call, because the mock consumes the arguments
+            let debug_args = debug_args(&input);
+            let result = {
+                let _running_guard = RunningGuard::new(self, id);
+                let mock = layered.mock.clone();
+                panic::catch_unwind(AssertUnwindSafe(|| mock.call(&info, input)))
+                    .unwrap_or_else(|payload| resume_mock_panic(payload, fn_name, debug_args))
+            };
+            if layered.mock.is_expired() {
+                retain(&mut self.mocks.borrow_mut(), id, |stored| {
+                    !stored.mock.is_same(&layered.mock)
+                });
+            }
+            match result {
+                MockLayerResult::Handled(result) => return result,
+                MockLayerResult::Unhandled(new_input) => input = new_input,
+            }
+            below_layer = layered.layer;
+        }
+    }
+
+    /// Starts a new scope and returns its generation, which is never reused
+    #[cfg(feature = "debug-checks")]
+    pub fn open_scope(&self) -> u64 {
+        let generation = self.next_generation.get();
+        self.next_generation.set(generation + 1);
+        self.scopes.borrow_mut().push(generation);
+        generation
+    }
+
+    #[cfg(feature = "debug-checks")]
+    pub fn close_scope(&self, generation: u64) {
+        self.scopes
+            .borrow_mut()
+            .retain(|scope| *scope != generation)
+    }
+
+    /// Generation of the innermost scope, which is still open
+    #[cfg(feature = "debug-checks")]
+    pub fn current_scope(&self) -> Option<u64> {
+        self.scopes.borrow().last().cloned()
+    }
+
+    #[cfg(feature = "debug-checks")]
+    pub fn is_scope_open(&self, generation: u64) -> bool {
+        self.scopes.borrow().contains(&generation)
+    }
+}
+
+fn retain(mocks: &mut IdMap<MockStack>, id: TypeId, filter: impl FnMut(&LayeredMock) -> bool) {
+    if let Some(stack) = mocks.get_mut(&id) {
+        stack.mocks.retain(filter);
+        if stack.mocks.is_empty() {
+            mocks.remove(&id);
+        }
     }
 }
 
@@ -71,40 +364,143 @@ impl MockStore {
 // add and remove layer
 //   inside mock closure
 
-impl Default for MockStore {
-    fn default() -> Self {
-        MockStore {
-            layers: RefCell::new(vec![MockLayer::default()]),
-        }
+/// Continues unwinding of a panic from a mock closure adding mocked function and its arguments
+/// to a text payload, so `#[should_panic(expected = ...)]` still matches the original message
+fn resume_mock_panic(payload: Box<dyn Any + Send>, fn_name: &str, debug_args: Option<String>) -> ! {
+    let message = match payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+    {
+        Some(message) => message,
+        None => panic::resume_unwind(payload),
+    };
+    match debug_args {
+        Some(args) => panic!(
+            "mock of {} called with {} panicked: {}",
+            fn_name, args, message
+        ),
+        None => panic!("mock of {} panicked: {}", fn_name, message),
+    }
+}
+
+/// Marks function as being handled by a mock until dropped, even if the mock panics
+struct RunningGuard<'a> {
+    store: &'a MockStore,
+}
+
+impl<'a> RunningGuard<'a> {
+    fn new(store: &'a MockStore, id: TypeId) -> Self {
+        store.running.borrow_mut().push(id);
+        RunningGuard { store }
+    }
+}
+
+impl<'a> Drop for RunningGuard<'a> {
+    fn drop(&mut self) {
+        self.store.running.borrow_mut().pop();
     }
 }
 
+/// Map keyed with `TypeId`s, which are already hashes, so they aren't hashed again
+type IdMap<V> = HashMap<TypeId, V, BuildHasherDefault<IdHasher>>;
+
 #[derive(Default)]
-pub struct MockLayer {
-    mocks: HashMap<TypeId, ErasedStoredMock>,
+struct IdHasher(u64);
+
+impl Hasher for IdHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        // `TypeId` may be written as raw bytes, fold them preserving their entropy
+        for byte in bytes {
+            self.0 = self.0.rotate_left(8) ^ u64::from(*byte);
+        }
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.0 ^= value
+    }
 }
 
-impl MockLayer {
-    fn clear(&mut self) {
-        self.mocks.clear()
+/// Identifies a single mock pushed to a layer
+pub struct MockHandle {
+    id: TypeId,
+    mock: ErasedStoredMock,
+}
+
+/// Mocks of a function from all layers ordered by layer index
+struct MockStack {
+    fn_name: &'static str,
+    mocks: Vec<LayeredMock>,
+    /// Calls of the function made since it was mocked, whichever layer handled them
+    calls: Cell<usize>,
+}
+
+impl MockStack {
+    fn new(fn_name: &'static str) -> Self {
+        MockStack {
+            fn_name,
+            mocks: Vec::new(),
+            calls: Cell::new(0),
+        }
     }
 
-    fn clear_id(&mut self, id: TypeId) {
-        self.mocks.remove(&id);
+    /// Counts a call of the function and returns its index
+    fn count_call(&self) -> usize {
+        let call_index = self.calls.get();
+        self.calls.set(call_index + 1);
+        call_index
     }
+}
+
+/// Layer pushed on top of the thread layer
+struct StoredLayer {
+    /// Identifies the layer independently of its index, which changes when layers below are removed
+    key: u64,
+    name: Option<&'static str>,
+    /// IDs of functions mocked in the layer and if their mocks should be reported when unused
+    ids: Vec<(TypeId, bool)>,
+}
+
+/// Mocks of a context, at most one for each function
+#[derive(Default)]
+pub struct MockLayer {
+    mocks: IdMap<ContextMock>,
+}
 
-    pub unsafe fn add<I: Tuple, O>(
+struct ContextMock {
+    fn_name: &'static str,
+    mock: ErasedStoredMock,
+    report_unused: bool,
+}
+
+impl MockLayer {
+    /// Mock with `times` set is removed after serving that many calls
+    pub unsafe fn add<I, O>(
         &mut self,
         id: TypeId,
-        mock: Box<dyn FnMut<I, Output = MockResult<I, O>> + 'static>,
+        fn_name: &'static str,
+        mock: Box<dyn DynMock<I, O> + 'static>,
+        times: Option<usize>,
+        report_unused: bool,
     ) {
-        let stored = StoredMock::new(mock).erase();
-        self.mocks.insert(id, stored);
+        let context_mock = ContextMock {
+            fn_name,
+            mock: ErasedStoredMock::new(mock, times),
+            report_unused,
+        };
+        self.mocks.insert(id, context_mock);
     }
+}
 
-    unsafe fn get(&self, id: TypeId) -> Option<ErasedStoredMock> {
-        self.mocks.get(&id).cloned()
-    }
+/// Mock stored in a function's stack together with index of the layer it belongs to
+#[derive(Clone)]
+struct LayeredMock {
+    layer: usize,
+    mock: ErasedStoredMock,
 }
 
 pub enum MockLayerResult<I, O> {
@@ -112,43 +508,93 @@ pub enum MockLayerResult<I, O> {
     Unhandled(I),
 }
 
+/// Stored mock with erased type of closure arguments and output
+///
+/// Guarantees that while mock is running it's not overwritten, destroyed, or called again
 #[derive(Clone)]
 struct ErasedStoredMock {
-    mock: StoredMock<(), ()>,
+    mock: Rc<dyn Erased>,
+    state: Rc<MockState>,
+    #[cfg(feature = "debug-checks")]
+    type_name: &'static str,
 }
 
-impl ErasedStoredMock {
-    unsafe fn call<I: Tuple, O>(self, input: I) -> MockLayerResult<I, O> {
-        let unerased: StoredMock<I, O> = transmute(self.mock);
-        unerased.call(input)
-    }
-}
+/// Implemented for all types, allows storing them as trait objects
+trait Erased {}
 
-/// Guarantees that while mock is running it's not overwritten, destroyed, or called again
-#[derive(Clone)]
-struct StoredMock<I: Tuple, O> {
-    mock: Rc<RefCell<Box<dyn FnMut<I, Output = MockResult<I, O>>>>>,
-}
+impl<T: ?Sized> Erased for T {}
+
+type BoxedMock<I, O> = Box<dyn DynMock<I, O>>;
 
-impl<I: Tuple, O> StoredMock<I, O> {
-    fn new(mock: Box<dyn FnMut<I, Output = MockResult<I, O>> + 'static>) -> Self {
-        StoredMock {
-            mock: Rc::new(RefCell::new(mock)),
+impl ErasedStoredMock {
+    fn new<'a, I: 'a, O: 'a>(mock: BoxedMock<I, O>, times: Option<usize>) -> Self {
+        let mock: Rc<dyn Erased + 'a> = Rc::new(RefCell::new(mock));
+        ErasedStoredMock {
+            // Lifetimes of arguments and output must be provided by the callers
+            mock: unsafe { transmute::<Rc<dyn Erased + 'a>, Rc<dyn Erased>>(mock) },
+            state: Rc::new(MockState::new(times)),
+            #[cfg(feature = "debug-checks")]
+            type_name: std::any::type_name::<(I, O)>(),
         }
     }
 
-    fn call(&self, input: I) -> MockLayerResult<I, O> {
-        match self.mock.try_borrow_mut() {
-            Ok(mut mock) => MockLayerResult::Handled(mock.call_mut(input)),
+    /// Types of arguments and output must be the same as when mock was created
+    unsafe fn call<I, O>(self, info: &dyn Fn() -> CallInfo, input: I) -> MockLayerResult<I, O> {
+        #[cfg(feature = "debug-checks")]
+        assert_eq!(
+            self.type_name,
+            std::any::type_name::<(I, O)>(),
+            "Mock called with arguments and output of different types than it was created for"
+        );
+        let calls_left = self.state.calls_left.get();
+        if calls_left == Some(0) {
+            return MockLayerResult::Unhandled(input);
+        }
+        let mock = &*(Rc::as_ptr(&self.mock) as *const RefCell<BoxedMock<I, O>>);
+        match mock.try_borrow_mut() {
+            Ok(mut mock) => {
+                self.state
+                    .calls_left
+                    .set(calls_left.map(|calls_left| calls_left - 1));
+                self.state.calls.set(self.state.calls.get() + 1);
+                MockLayerResult::Handled(mock.call_mock(info, input))
+            }
             Err(_) => MockLayerResult::Unhandled(input),
         }
     }
 
-    fn erase(self) -> ErasedStoredMock {
-        unsafe {
-            ErasedStoredMock {
-                mock: transmute(self),
-            }
+    /// Number of calls handled by the mock so far
+    fn calls(&self) -> usize {
+        self.state.calls.get()
+    }
+
+    fn is_expired(&self) -> bool {
+        self.state.calls_left.get() == Some(0)
+    }
+
+    fn is_same(&self, other: &ErasedStoredMock) -> bool {
+        Rc::ptr_eq(&self.state, &other.state)
+    }
+}
+
+/// State shared by all copies of a stored mock, counts the mock as alive in `MOCK_COUNT`
+struct MockState {
+    calls_left: Cell<Option<usize>>,
+    calls: Cell<usize>,
+}
+
+impl MockState {
+    fn new(calls_left: Option<usize>) -> Self {
+        MOCK_COUNT.fetch_add(1, Ordering::Relaxed);
+        MockState {
+            calls_left: Cell::new(calls_left),
+            calls: Cell::new(0),
         }
     }
 }
+
+impl Drop for MockState {
+    fn drop(&mut self) {
+        MOCK_COUNT.fetch_sub(1, Ordering::Relaxed);
+    }
+}
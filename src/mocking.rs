@@ -1,14 +1,22 @@
-use crate::mock_store::{MockLayer, MockStore};
-use std::{any::{Any, TypeId}, marker::Tuple};
+use crate::mock_fn::{DynMock, FnMock, InfoFnMock};
+pub use crate::mock_fn::{MockArgs, MockFn, MockFnOnce, MockInfoFn, PrependInfo};
+use crate::mock_store::{MockHandle, MockLayer, MockStore};
+#[cfg(feature = "registry")]
+pub use crate::registry::{registry, RegisteredFn};
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::fmt;
 use std::marker::PhantomData;
-use std::mem::transmute;
+use std::mem::{size_of, transmute, ManuallyDrop};
+use std::rc::Rc;
+use std::thread::{self, Thread};
 
 /// Trait for setting up mocks
 ///
 /// The trait is implemented for all functions, so its methods can be called on any function.
 ///
 /// Note: methods have any effect only if called on functions [annotated as mockable](https://docs.rs/mocktopus_macros).
-pub trait Mockable<T: Tuple, O> {
+pub trait Mockable<T: MockArgs, O> {
     /// Core function for setting up mocks
     ///
     /// Always consider using [mock_safe](#tymethod.mock_safe) or [MockContext](struct.MockContext.html).
@@ -21,6 +29,13 @@ pub trait Mockable<T: Tuple, O> {
     /// [thread local static storage](https://doc.rust-lang.org/std/macro.thread_local.html),
     /// so it has effect only in thread, where it was set.
     /// Each Rust test is executed in separate thread, so mocks do not leak between them.
+    ///
+    /// The mock is set in the innermost mock layer, which is the layer of the innermost running
+    /// [MockContext](struct.MockContext.html) or the innermost pushed [NamedLayer](struct.NamedLayer.html).
+    /// It replaces the function's mock in that layer and is removed together with the layer.
+    /// Outside of any of them the mock is set for the whole thread.
+    /// The same applies to all the other methods setting up mocks except for
+    /// [mock_thread_wide](#tymethod.mock_thread_wide).
     /// # Safety
     /// It is up to the user to make sure, that the closure is valid long enough to serve all calls to mocked function.
     /// If the mock closure uses any non-static values or references, it will silently become invalid at some point of
@@ -42,7 +57,9 @@ pub trait Mockable<T: Tuple, O> {
     ///     assert_eq!("mocked", get_string(&Context::default()));
     /// }
     /// ```
-    unsafe fn mock_raw<M: FnMut<T, Output = MockResult<T, O>>>(&self, mock: M);
+    /// With `debug-checks` feature enabled the mock is bound to the innermost living [MockScope](struct.MockScope.html)
+    /// and panics when called after the scope ends.
+    unsafe fn mock_raw<M: MockFn<T, O>>(&self, mock: M);
 
     /// A safe variant of [mock_raw](#tymethod.mock_raw) for static closures
     ///
@@ -62,16 +79,149 @@ pub trait Mockable<T: Tuple, O> {
     ///     assert_eq!("mocked", get_string());
     /// }
     /// ```
-    fn mock_safe<M: FnMut<T, Output = MockResult<T, O>> + 'static>(&self, mock: M);
+    fn mock_safe<M: MockFn<T, O> + 'static>(&self, mock: M);
+
+    /// A variant of [mock_safe](#tymethod.mock_safe), which always sets the mock for the whole thread
+    ///
+    /// The mock stays after all the currently running `MockContext`s end,
+    /// but while they run their mocks of the function shadow it.
+    ///
+    /// ```
+    /// #[test]
+    /// fn get_string_test() {
+    ///     MockContext::new().run(|| {
+    ///         get_string.mock_thread_wide(|| MockResult::Return("mocked".to_string()));
+    ///     });
+    ///
+    ///     assert_eq!("mocked", get_string());
+    /// }
+    /// ```
+    fn mock_thread_wide<M: MockFn<T, O> + 'static>(&self, mock: M);
+
+    /// A variant of [mock_safe](#tymethod.mock_safe) for closures taking arguments as a struct with named fields
+    ///
+    /// For every mockable non-generic function `f` a struct `f_args` is generated with fields named after
+    /// function arguments. For methods the struct is named `Type_method_args` and the receiver is stored in `self_`.
+    /// Unlike tuples, the fields do not silently change meaning when function arguments get reordered.
+    ///
+    /// ```
+    /// #[mockable]
+    /// fn connect(host: &str, port: u16, timeout: u32) -> bool {
+    ///     false
+    /// }
+    ///
+    /// #[test]
+    /// fn connect_test() {
+    ///     connect.mock_named(|args: connect_args| match args.timeout {
+    ///         0 => MockResult::Return(true),
+    ///         _ => MockResult::Continue(args),
+    ///     });
+    ///
+    ///     assert_eq!(true, connect("localhost", 80, 0));
+    /// }
+    /// ```
+    fn mock_named<A: NamedArgs<T>, M: FnMut(A) -> MockResult<A, O> + 'static>(&self, mock: M);
+
+    /// A variant of [mock_safe](#tymethod.mock_safe), which closure receives [CallInfo](struct.CallInfo.html)
+    /// before the function arguments
+    ///
+    /// Available for functions with up to 16 arguments.
+    ///
+    /// ```
+    /// #[mockable]
+    /// fn get_number() -> usize {
+    ///     0
+    /// }
+    ///
+    /// #[test]
+    /// fn get_number_test() {
+    ///     get_number.mock_with_info(|info: &CallInfo| MockResult::Return(info.call_index() + 10));
+    ///
+    ///     assert_eq!(10, get_number());
+    ///     assert_eq!(11, get_number());
+    /// }
+    /// ```
+    #[cfg(feature = "nightly")]
+    fn mock_with_info<M>(&self, mock: M)
+    where
+        T: for<'i> PrependInfo<'i>,
+        M: for<'i> FnMut<<T as PrependInfo<'i>>::Args, Output = MockResult<T, O>> + 'static;
+
+    /// A variant of [mock_safe](#tymethod.mock_safe), which closure receives [CallInfo](struct.CallInfo.html)
+    /// before the function arguments
+    ///
+    /// Available for functions with up to 16 arguments.
+    /// Types of the closure arguments must be annotated, e.g. `|info: &CallInfo, a: u32|`.
+    #[cfg(not(feature = "nightly"))]
+    fn mock_with_info<M: MockInfoFn<T, O> + 'static>(&self, mock: M)
+    where
+        T: for<'i> PrependInfo<'i>;
+
+    /// A variant of [mock_safe](#tymethod.mock_safe), which serves only a single call
+    ///
+    /// The closure is called at most once, so it can move captured values out.
+    /// After the call the mock is removed and the function runs normally.
+    ///
+    /// ```
+    /// #[mockable]
+    /// fn get_string() -> String {
+    ///     "not mocked".to_string()
+    /// }
+    ///
+    /// #[test]
+    /// fn get_string_test() {
+    ///     let mocked = "mocked".to_string();
+    ///     get_string.mock_once(move || MockResult::Return(mocked));
+    ///
+    ///     assert_eq!("mocked", get_string());
+    ///     assert_eq!("not mocked", get_string());
+    /// }
+    /// ```
+    fn mock_once<M: MockFnOnce<T, O> + 'static>(&self, mock: M);
+
+    /// A variant of [mock_safe](#tymethod.mock_safe), which serves only the given number of calls
+    ///
+    /// After the last allowed call the mock is removed and the function runs normally.
+    fn mock_times<M: MockFn<T, O> + 'static>(&self, times: usize, mock: M);
+
+    /// A variant of [mock_safe](#tymethod.mock_safe) for non-static closures, which mocks only while `scope` runs
+    ///
+    /// The mock is set on top of other mocks of the function in the innermost layer.
+    /// When `scope` returns or panics, exactly this mock is removed and the mock it shadowed is used again.
+    /// The closure may borrow local values, because it's removed before `mock_scoped` returns.
+    ///
+    /// ```
+    /// #[mockable]
+    /// fn get_string() -> String {
+    ///     "not mocked".to_string()
+    /// }
+    ///
+    /// #[test]
+    /// fn get_string_test() {
+    ///     let mocked = "mocked".to_string();
+    ///     get_string.mock_scoped(
+    ///         || MockResult::Return(mocked.clone()),
+    ///         || assert_eq!("mocked", get_string()),
+    ///     );
+    ///
+    ///     assert_eq!("not mocked", get_string());
+    /// }
+    /// ```
+    fn mock_scoped<M: MockFn<T, O>, R, S: FnOnce() -> R>(&self, mock: M, scope: S) -> R;
 
     /// Stop mocking this function.
     ///
     /// All future invocations will be forwarded to the real implementation.
     fn clear_mock(&self);
 
+    /// Checks if the function is mocked in the current thread
+    ///
+    /// Mocks of any kind set up for the whole thread or for a running `MockContext` are taken into account.
+    fn is_mocked(&self) -> bool;
+
     #[doc(hidden)]
     /// Called before every execution of a mockable function. Checks if mock is set and if it is, calls it.
-    fn call_mock(&self, input: T) -> MockResult<T, O>;
+    fn call_mock(&self, input: T, debug_args: &dyn Fn(&T) -> Option<String>) -> MockResult<T, O>;
 
     #[doc(hidden)]
     /// Returns a unique ID of the function, which is used to set and get its mock.
@@ -86,6 +236,252 @@ pub enum MockResult<T, O> {
 
     /// Function returns immediately with a given value. The returned value is passed inside enum variant.
     Return(O),
+
+    /// Function runs normally as if it was called with given arguments and its result is passed through the closure.
+    /// The arguments are passed inside enum variant as a tuple followed by the boxed closure,
+    /// see [continue_then](#method.continue_then).
+    ContinueThen(T, Box<dyn FnOnce(O) -> O>),
+}
+
+impl<T, O> MockResult<T, O> {
+    /// Creates `ContinueThen` variant, which lets mock observe and modify the value returned from the function
+    ///
+    /// The type of closure argument usually must be annotated, because it can't be inferred.
+    /// It can't be used for functions returning `!` or annotated with `#[track_caller]`,
+    /// they panic when the mock returns it.
+    ///
+    /// ```
+    /// #[mockable]
+    /// fn parse(input: &str) -> Vec<u32> {
+    ///     input.split(',').map(|item| item.parse().unwrap()).collect()
+    /// }
+    ///
+    /// #[test]
+    /// fn parse_test() {
+    ///     parse.mock_safe(|input| MockResult::continue_then((input,), |mut parsed: Vec<u32>| {
+    ///         parsed[0] = 0;
+    ///         parsed
+    ///     }));
+    ///
+    ///     assert_eq!(vec![0, 2], parse("1,2"));
+    /// }
+    /// ```
+    pub fn continue_then(args: T, then: impl FnOnce(O) -> O + 'static) -> Self {
+        MockResult::ContinueThen(args, Box::new(then))
+    }
+}
+
+#[doc(hidden)]
+/// Runs the original function body, which was wrapped in a closure
+pub fn run_original<O, F: FnOnce() -> O>(original: F) -> O {
+    original()
+}
+
+#[doc(hidden)]
+/// Passes the original function result through the closure returned from mock
+/// # Safety
+/// Types `O` and `R` must differ only in lifetimes
+pub unsafe fn run_then<O, R>(then: Box<dyn FnOnce(O) -> O>, result: R) -> R {
+    cast(then(cast(result)))
+}
+
+#[doc(hidden)]
+/// Fails if mock returned `ContinueThen` for a function, whose result can't be passed through a closure
+pub fn reject_then<O>(then: Option<Box<dyn FnOnce(O) -> O>>) {
+    if then.is_some() {
+        panic!(
+            "MockResult::ContinueThen can't be used for functions returning `!` \
+            or annotated with `#[track_caller]`"
+        )
+    }
+}
+
+#[doc(hidden)]
+/// Moves value into a type, which differs only in lifetimes
+///
+/// Signatures of trait methods implementations may have different lifetimes than trait declarations,
+/// so arguments and results of mocks must be moved between them.
+/// # Safety
+/// Types `A` and `B` must differ only in lifetimes
+pub unsafe fn cast<A, B>(value: A) -> B {
+    union Cast<A, B> {
+        from: ManuallyDrop<A>,
+        to: ManuallyDrop<B>,
+    }
+    assert_eq!(
+        size_of::<A>(),
+        size_of::<B>(),
+        "Mocktopus internal error: cast between different types"
+    );
+    ManuallyDrop::into_inner(
+        Cast {
+            from: ManuallyDrop::new(value),
+        }
+        .to,
+    )
+}
+
+/// Conversion between a tuple of function arguments and a struct with named fields
+///
+/// Implemented by the structs generated for [mock_named](trait.Mockable.html#tymethod.mock_named).
+pub trait NamedArgs<T> {
+    /// Creates struct from a tuple of arguments
+    fn from_tuple(args: T) -> Self;
+
+    /// Converts struct back into a tuple of arguments
+    fn into_tuple(self) -> T;
+}
+
+/// Information about a call of a mocked function
+///
+/// Passed to mock closures set up with [mock_with_info](trait.Mockable.html#tymethod.mock_with_info).
+#[derive(Clone, Debug)]
+pub struct CallInfo {
+    call_index: usize,
+    fn_name: &'static str,
+    layer_depth: usize,
+    thread: Thread,
+    reentrant: bool,
+}
+
+impl CallInfo {
+    pub(crate) fn new(
+        call_index: usize,
+        fn_name: &'static str,
+        layer_depth: usize,
+        reentrant: bool,
+    ) -> Self {
+        CallInfo {
+            call_index,
+            fn_name,
+            layer_depth,
+            thread: thread::current(),
+            reentrant,
+        }
+    }
+
+    /// Zero-based index of the call among the calls of the function made since it was mocked.
+    /// Calls handled by mocks in any layer are counted. Counting restarts when all mocks of the function
+    /// are removed.
+    pub fn call_index(&self) -> usize {
+        self.call_index
+    }
+
+    /// Name of the mocked function as returned by `std::any::type_name`
+    pub fn fn_name(&self) -> &'static str {
+        self.fn_name
+    }
+
+    /// Depth of the mock layer, which handled the call.
+    /// Mocks set up for the whole thread have depth 0, mocks of a `MockContext` have depth 1
+    /// and mocks of contexts run inside it have depth one higher than their parent context.
+    pub fn layer_depth(&self) -> usize {
+        self.layer_depth
+    }
+
+    /// Thread, which called the function
+    pub fn thread(&self) -> &Thread {
+        &self.thread
+    }
+
+    /// True if the function was called while another call of it was being handled by a mock
+    pub fn is_reentrant(&self) -> bool {
+        self.reentrant
+    }
+}
+
+/// Adapts mock closure taking `NamedArgs` to a closure taking a tuple of arguments
+struct NamedArgsMock<A, M> {
+    mock: M,
+    args: PhantomData<fn(A) -> A>,
+}
+
+impl<T, O, A: NamedArgs<T>, M: FnMut(A) -> MockResult<A, O>> DynMock<T, O> for NamedArgsMock<A, M> {
+    fn call_mock(&mut self, _: &dyn Fn() -> CallInfo, args: T) -> MockResult<T, O> {
+        match (self.mock)(A::from_tuple(args)) {
+            MockResult::Continue(args) => MockResult::Continue(args.into_tuple()),
+            MockResult::Return(result) => MockResult::Return(result),
+            MockResult::ContinueThen(args, then) => {
+                MockResult::ContinueThen(args.into_tuple(), then)
+            }
+        }
+    }
+}
+
+/// Adapts `FnOnce` mock closure to `FnMut`, which must be called at most once
+struct OnceMock<M> {
+    mock: Option<M>,
+}
+
+impl<T: MockArgs, O, M: MockFnOnce<T, O>> DynMock<T, O> for OnceMock<M> {
+    fn call_mock(&mut self, _: &dyn Fn() -> CallInfo, args: T) -> MockResult<T, O> {
+        let mock = self.mock.take().expect("Mock called more than once");
+        mock.call_mock_fn_once(args)
+    }
+}
+
+/// Mock must be valid long enough to serve all calls to mocked function
+unsafe fn add_to_innermost_layer<T: MockArgs, O, F: Mockable<T, O>, M: DynMock<T, O>>(
+    mockable: &F,
+    mock: M,
+    times: Option<usize>,
+) {
+    let (id, fn_name, mock) = erase_mock(mockable, mock);
+    MOCK_STORE.with(|mock_store| mock_store.add_to_innermost_layer(id, fn_name, mock, times))
+}
+
+/// Mock must be valid long enough to serve all calls to mocked function
+unsafe fn add_to_thread_layer<T: MockArgs, O, F: Mockable<T, O>, M: DynMock<T, O>>(
+    mockable: &F,
+    mock: M,
+) {
+    let (id, fn_name, mock) = erase_mock(mockable, mock);
+    MOCK_STORE.with(|mock_store| mock_store.add_to_thread_layer(id, fn_name, mock, None))
+}
+
+/// Boxes the mock with erased lifetime together with ID and name of the mocked function
+unsafe fn erase_mock<T: MockArgs, O, F: Mockable<T, O>, M: DynMock<T, O>>(
+    mockable: &F,
+    mock: M,
+) -> (TypeId, &'static str, Box<dyn DynMock<T, O> + 'static>) {
+    let id = mockable.get_mock_id();
+    let fn_name = std::any::type_name::<F>();
+    #[cfg(feature = "registry")]
+    crate::registry::check_registered(fn_name);
+    let boxed = Box::new(mock) as Box<dyn DynMock<T, O> + '_>;
+    let static_boxed: Box<dyn DynMock<T, O> + 'static> = transmute(boxed);
+    (id, fn_name, static_boxed)
+}
+
+/// Adapts mock closure set up with `mock_raw` to panic when called after the innermost `MockScope` ends
+#[cfg(feature = "debug-checks")]
+struct ScopedMock<M> {
+    mock: M,
+    scope: Option<u64>,
+}
+
+#[cfg(feature = "debug-checks")]
+impl<M> ScopedMock<M> {
+    fn new(mock: M) -> Self {
+        ScopedMock {
+            mock,
+            scope: MOCK_STORE.with(|mock_store| mock_store.current_scope()),
+        }
+    }
+}
+
+#[cfg(feature = "debug-checks")]
+impl<T, O, M: DynMock<T, O>> DynMock<T, O> for ScopedMock<M> {
+    fn call_mock(&mut self, info: &dyn Fn() -> CallInfo, args: T) -> MockResult<T, O> {
+        if let Some(scope) = self.scope {
+            let is_open = MOCK_STORE.with(|mock_store| mock_store.is_scope_open(scope));
+            assert!(
+                is_open,
+                "Mock set up with mock_raw called after its MockScope ended"
+            );
+        }
+        self.mock.call_mock(info, args)
+    }
 }
 
 thread_local! {
@@ -97,35 +493,309 @@ pub fn clear_mocks() {
     MOCK_STORE.with(|mock_store| mock_store.clear())
 }
 
-impl<T: Tuple, O, F: FnOnce<T, Output = O>> Mockable<T, O> for F {
-    unsafe fn mock_raw<M: FnMut<T, Output = MockResult<T, O>>>(&self, mock: M) {
-        let id = self.get_mock_id();
-        let boxed = Box::new(mock) as Box<dyn FnMut<_, Output = _>>;
-        let static_boxed: Box<dyn FnMut<T, Output = MockResult<T, O>> + 'static> = transmute(boxed);
-        MOCK_STORE.with(|mock_store| mock_store.add_to_thread_layer(id, static_boxed))
+/// Lists functions mocked in the current thread
+///
+/// The result can be printed with `{}` to see mocks of every layer, e.g. in a failure message.
+pub fn active_mocks() -> ActiveMocks {
+    MOCK_STORE.with(|mock_store| mock_store.active_mocks())
+}
+
+/// Returns the mock layer named `name`, which can be pushed on top of the current layers,
+/// have mocks added or be cleared
+///
+/// Named layers let mocks of shared fixtures, test-local overrides and per-step scenarios
+/// be stacked and torn down independently:
+///
+/// ```
+/// #[test]
+/// fn scenario_test() {
+///     let _fixture = layer("fixture").push();
+///     layer("fixture").mock_safe(get_config, || MockResult::Return(Config::default()));
+///     layer("fixture").mock_safe(load_user, |_| MockResult::Return(None));
+///
+///     let step = layer("step").push();
+///     layer("step").mock_safe(load_user, |id| MockResult::Return(Some(User::new(id))));
+///     assert!(is_admin(1));
+///     drop(step);
+///
+///     assert!(!is_admin(1));
+/// }
+/// ```
+pub fn layer(name: &'static str) -> NamedLayer {
+    NamedLayer { name }
+}
+
+/// Removes all mocks of the innermost mock layer named `name`, but keeps the layer
+///
+/// # Panics
+/// Panics if no layer named `name` is pushed
+pub fn clear_layer(name: &'static str) {
+    layer(name).clear()
+}
+
+/// Mock layer identified by name, returned by [layer](fn.layer.html)
+///
+/// Mocks are added to and cleared from the innermost pushed layer with the name.
+#[derive(Clone, Copy, Debug)]
+pub struct NamedLayer {
+    name: &'static str,
+}
+
+impl NamedLayer {
+    /// Pushes a new empty layer with the name on top of all the current layers of the thread.
+    ///
+    /// Mocks of upper layers are tried before mocks of lower layers, the same as with
+    /// [MockContext](struct.MockContext.html), which layers are pushed the same way.
+    /// The layer is removed together with its mocks when the returned guard is dropped.
+    pub fn push(&self) -> NamedLayerGuard {
+        let key = MOCK_STORE.with(|mock_store| unsafe {
+            mock_store.add_layer(MockLayer::default(), Some(self.name))
+        });
+        NamedLayerGuard {
+            key,
+            phantom_not_send: PhantomData,
+        }
+    }
+
+    /// Mocks the function in the layer, replacing its previous mock in the layer if there was one.
+    ///
+    /// # Panics
+    /// Panics if no layer with the name is pushed
+    pub fn mock_safe<T: MockArgs, O, F, M>(&self, mockable: F, mock: M)
+    where
+        F: Mockable<T, O>,
+        M: MockFn<T, O> + 'static,
+    {
+        let key = self.key();
+        let fn_name = std::any::type_name::<F>();
+        #[cfg(feature = "registry")]
+        crate::registry::check_registered(fn_name);
+        unsafe {
+            let id = mockable.get_mock_id();
+            MOCK_STORE.with(|mock_store| {
+                mock_store.add_to_layer(key, id, fn_name, Box::new(FnMock(mock)), None)
+            })
+        }
     }
 
-    fn mock_safe<M: FnMut<T, Output = MockResult<T, O>> + 'static>(&self, mock: M) {
-        unsafe { self.mock_raw(mock) }
+    /// Removes all mocks of the layer, but keeps the layer
+    ///
+    /// # Panics
+    /// Panics if no layer with the name is pushed
+    pub fn clear(&self) {
+        let key = self.key();
+        MOCK_STORE.with(|mock_store| mock_store.clear_layer(key))
     }
 
-    fn clear_mock(&self) {
-        let id = unsafe { self.get_mock_id() };
-        MOCK_STORE.with(|mock_store| mock_store.clear_id(id))
+    /// Name of the layer
+    pub fn name(&self) -> &'static str {
+        self.name
     }
 
-    fn call_mock(&self, input: T) -> MockResult<T, O> {
-        unsafe {
-            let id = self.get_mock_id();
-            MOCK_STORE.with(|mock_store| mock_store.call(id, input))
+    fn key(&self) -> u64 {
+        MOCK_STORE
+            .with(|mock_store| mock_store.find_layer(self.name))
+            .unwrap_or_else(|| panic!("No mock layer named `{}` is pushed", self.name))
+    }
+}
+
+/// Keeps a layer pushed with [NamedLayer::push](struct.NamedLayer.html#method.push) in place
+/// and removes it together with its mocks on drop
+///
+/// Guards can be dropped in any order, the layers above the removed one keep their order.
+#[must_use = "the layer is removed when the guard is dropped"]
+pub struct NamedLayerGuard {
+    key: u64,
+    phantom_not_send: PhantomData<*const ()>,
+}
+
+impl Drop for NamedLayerGuard {
+    fn drop(&mut self) {
+        // The store may be already gone if the guard is dropped during thread teardown
+        let _ = MOCK_STORE.try_with(|mock_store| mock_store.remove_layer(self.key));
+    }
+}
+
+/// Functions mocked in a thread grouped by mock layers, returned by [active_mocks](fn.active_mocks.html)
+#[derive(Clone, Debug)]
+pub struct ActiveMocks {
+    layers: Vec<MockLayerInfo>,
+}
+
+impl ActiveMocks {
+    pub(crate) fn new(layers: Vec<MockLayerInfo>) -> Self {
+        ActiveMocks { layers }
+    }
+
+    /// Layers from the one with mocks set up for the whole thread to the innermost running `MockContext`
+    pub fn layers(&self) -> &[MockLayerInfo] {
+        &self.layers
+    }
+}
+
+impl fmt::Display for ActiveMocks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for layer in &self.layers {
+            match (layer.depth, layer.name) {
+                (0, _) => writeln!(f, "thread mocks:")?,
+                (depth, Some(name)) => writeln!(f, "layer {} `{}` mocks:", depth, name)?,
+                (depth, None) => writeln!(f, "MockContext {} mocks:", depth)?,
+            }
+            for fn_name in &layer.fn_names {
+                writeln!(f, "    {}", fn_name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Functions mocked in a single mock layer
+#[derive(Clone, Debug)]
+pub struct MockLayerInfo {
+    depth: usize,
+    name: Option<&'static str>,
+    fn_names: Vec<&'static str>,
+}
+
+impl MockLayerInfo {
+    pub(crate) fn new(
+        depth: usize,
+        name: Option<&'static str>,
+        fn_names: Vec<&'static str>,
+    ) -> Self {
+        MockLayerInfo {
+            depth,
+            name,
+            fn_names,
         }
     }
 
-    unsafe fn get_mock_id(&self) -> TypeId {
-        (|| ()).type_id()
+    /// Depth of the layer, the same as reported by [CallInfo](struct.CallInfo.html#method.layer_depth)
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Name of the layer pushed with [NamedLayer::push](struct.NamedLayer.html#method.push),
+    /// `None` for the thread layer and `MockContext` layers
+    pub fn name(&self) -> Option<&'static str> {
+        self.name
+    }
+
+    /// Names of the mocked functions as returned by `std::any::type_name`, sorted
+    pub fn fn_names(&self) -> &[&'static str] {
+        &self.fn_names
     }
 }
 
+/// Implements `Mockable` for functions `F` with arguments `$args`
+macro_rules! impl_mockable {
+    ([$($generics:tt)*] [$($fn_bound:tt)*] $args:ty) => {
+        impl<$($generics)* O, F: $($fn_bound)*> Mockable<$args, O> for F {
+            unsafe fn mock_raw<M: MockFn<$args, O>>(&self, mock: M) {
+                #[cfg(feature = "debug-checks")]
+                let mock = ScopedMock::new(FnMock(mock));
+                #[cfg(not(feature = "debug-checks"))]
+                let mock = FnMock(mock);
+                add_to_innermost_layer(self, mock, None)
+            }
+
+            fn mock_safe<M: MockFn<$args, O> + 'static>(&self, mock: M) {
+                unsafe { add_to_innermost_layer(self, FnMock(mock), None) }
+            }
+
+            fn mock_thread_wide<M: MockFn<$args, O> + 'static>(&self, mock: M) {
+                unsafe { add_to_thread_layer(self, FnMock(mock)) }
+            }
+
+            fn mock_named<A: NamedArgs<$args>, M: FnMut(A) -> MockResult<A, O> + 'static>(&self, mock: M) {
+                // The closure is static, arguments struct is only passed through it
+                unsafe {
+                    let mock = NamedArgsMock {
+                        mock,
+                        args: PhantomData,
+                    };
+                    add_to_innermost_layer(self, mock, None)
+                }
+            }
+
+            #[cfg(feature = "nightly")]
+            fn mock_with_info<M>(&self, mock: M)
+            where
+                $args: for<'i> PrependInfo<'i>,
+                M: for<'i> FnMut<<$args as PrependInfo<'i>>::Args, Output = MockResult<$args, O>>
+                    + 'static,
+            {
+                unsafe { add_to_innermost_layer(self, InfoFnMock(mock), None) }
+            }
+
+            #[cfg(not(feature = "nightly"))]
+            fn mock_with_info<M: MockInfoFn<$args, O> + 'static>(&self, mock: M)
+            where
+                $args: for<'i> PrependInfo<'i>,
+            {
+                unsafe { add_to_innermost_layer(self, InfoFnMock(mock), None) }
+            }
+
+            fn mock_once<M: MockFnOnce<$args, O> + 'static>(&self, mock: M) {
+                let mock = OnceMock { mock: Some(mock) };
+                unsafe { add_to_innermost_layer(self, mock, Some(1)) }
+            }
+
+            fn mock_times<M: MockFn<$args, O> + 'static>(&self, times: usize, mock: M) {
+                let mock = FnMock(mock);
+                unsafe { add_to_innermost_layer(self, mock, Some(times)) }
+            }
+
+            fn mock_scoped<M: MockFn<$args, O>, R, S: FnOnce() -> R>(&self, mock: M, scope: S) -> R {
+                // The guard removes the mock even if the scope panics, so it can't outlive its captures
+                let _guard = unsafe {
+                    let (id, fn_name, mock) = erase_mock(self, FnMock(mock));
+                    ScopedMockGuard {
+                        handle: MOCK_STORE.with(|mock_store| {
+                            mock_store.push_to_innermost_layer(id, fn_name, mock)
+                        }),
+                    }
+                };
+                scope()
+            }
+
+            fn clear_mock(&self) {
+                let id = unsafe { self.get_mock_id() };
+                MOCK_STORE.with(|mock_store| mock_store.clear_id(id))
+            }
+
+            fn is_mocked(&self) -> bool {
+                let id = unsafe { self.get_mock_id() };
+                MOCK_STORE.with(|mock_store| mock_store.is_mocked(id))
+            }
+
+            fn call_mock(&self, input: $args, debug_args: &dyn Fn(&$args) -> Option<String>) -> MockResult<$args, O> {
+                unsafe {
+                    let id = self.get_mock_id();
+                    MOCK_STORE.with(|mock_store| mock_store.call(id, input, debug_args))
+                }
+            }
+
+            unsafe fn get_mock_id(&self) -> TypeId {
+                (|| ()).type_id()
+            }
+        }
+    };
+}
+
+#[cfg(feature = "nightly")]
+impl_mockable!([T: MockArgs,] [FnOnce<T, Output = O>] T);
+
+#[cfg(not(feature = "nightly"))]
+macro_rules! impl_mockable_for_arity {
+    ($($arg:ident),*) => {
+        impl_mockable!([$($arg,)*] [FnOnce($($arg),*) -> O] ($($arg,)*));
+    };
+}
+
+#[cfg(not(feature = "nightly"))]
+crate::mock_fn::for_all_arities!(impl_mockable_for_arity);
+
 /// `MockContext` allows for safe capture of local variables.
 ///
 /// It does this by forcing only mocking the actual function while in the body
@@ -170,9 +840,14 @@ impl<T: Tuple, O, F: FnOnce<T, Output = O>> Mockable<T, O> for F {
 ///     });
 /// assert_eq!(count, 1);
 /// ```
+///
+/// When `run` returns, every mock, which was never called, is reported with a warning
+/// naming the mocked function, see [deny_unused](#method.deny_unused).
 #[derive(Default)]
 pub struct MockContext<'a> {
     mock_layer: MockLayer,
+    deny_unused: bool,
+    adding_shared_mocks: bool,
     phantom_lifetime: PhantomData<&'a ()>,
 }
 
@@ -186,30 +861,234 @@ impl<'a> MockContext<'a> {
     ///
     /// This function doesn't actually mock the function.  It registers it as a
     /// function that will be mocked when [`run`](#method.run) is called.
-    pub fn mock_safe<I: Tuple, O, F, M>(self, mockable: F, mock: M) -> Self
+    pub fn mock_safe<I: MockArgs, O, F, M>(self, mockable: F, mock: M) -> Self
     where
         F: Mockable<I, O>,
-        M: FnMut<I, Output = MockResult<I, O>> + 'a,
+        M: MockFn<I, O> + 'a,
     {
-        unsafe { self.mock_raw(mockable, mock) }
+        unsafe { self.add(mockable, FnMock(mock), None) }
     }
 
     /// Set up a function to be mocked.
     ///
     /// This is an unsafe version of [`mock_safe`](#method.mock_safe),
     /// without lifetime constraint on mock
-    pub unsafe fn mock_raw<I: Tuple, O, F, M>(mut self, mockable: F, mock: M) -> Self
+    /// # Safety
+    /// It is up to the user to make sure, that the closure is valid long enough to serve all calls to
+    /// mocked function during [`run`](#method.run).
+    ///
+    /// With `debug-checks` feature enabled the mock is bound to the innermost living [MockScope](struct.MockScope.html)
+    /// and panics when called after the scope ends.
+    pub unsafe fn mock_raw<I: MockArgs, O, F, M>(self, mockable: F, mock: M) -> Self
+    where
+        F: Mockable<I, O>,
+        M: MockFn<I, O>,
+    {
+        #[cfg(feature = "debug-checks")]
+        let mock = ScopedMock::new(FnMock(mock));
+        #[cfg(not(feature = "debug-checks"))]
+        let mock = FnMock(mock);
+        self.add(mockable, mock, None)
+    }
+
+    /// Set up a function to be mocked only for a single call.
+    ///
+    /// The closure is called at most once, so it can move captured values out.
+    /// After the call the mock is removed and the function runs as if it was not mocked in this context.
+    pub fn mock_once<I: MockArgs, O, F, M>(self, mockable: F, mock: M) -> Self
     where
         F: Mockable<I, O>,
-        M: FnMut<I, Output = MockResult<I, O>>,
+        M: MockFnOnce<I, O> + 'a,
     {
-        let mock_box = Box::new(mock) as Box<dyn FnMut<_, Output = _>>;
-        let mock_box_static: Box<dyn FnMut<I, Output = MockResult<I, O>> + 'static> =
-            std::mem::transmute(mock_box);
-        self.mock_layer.add(mockable.get_mock_id(), mock_box_static);
+        unsafe { self.add(mockable, OnceMock { mock: Some(mock) }, Some(1)) }
+    }
+
+    /// Set up a function to be mocked only for the given number of calls.
+    ///
+    /// After the last allowed call the mock is removed and the function runs as if it was not mocked in this context.
+    pub fn mock_times<I: MockArgs, O, F, M>(self, mockable: F, times: usize, mock: M) -> Self
+    where
+        F: Mockable<I, O>,
+        M: MockFn<I, O> + 'a,
+    {
+        unsafe { self.add(mockable, FnMock(mock), Some(times)) }
+    }
+
+    /// Set up a function to be mocked with a closure, which receives [CallInfo](struct.CallInfo.html)
+    /// before the function arguments.
+    ///
+    /// Available for functions with up to 16 arguments.
+    #[cfg(feature = "nightly")]
+    pub fn mock_with_info<I, O, F, M>(self, mockable: F, mock: M) -> Self
+    where
+        F: Mockable<I, O>,
+        I: MockArgs + for<'i> PrependInfo<'i>,
+        M: for<'i> FnMut<<I as PrependInfo<'i>>::Args, Output = MockResult<I, O>> + 'a,
+    {
+        unsafe { self.add(mockable, InfoFnMock(mock), None) }
+    }
+
+    /// Set up a function to be mocked with a closure, which receives [CallInfo](struct.CallInfo.html)
+    /// before the function arguments.
+    ///
+    /// Available for functions with up to 16 arguments.
+    /// Types of the closure arguments must be annotated, e.g. `|info: &CallInfo, a: u32|`.
+    #[cfg(not(feature = "nightly"))]
+    pub fn mock_with_info<I, O, F, M>(self, mockable: F, mock: M) -> Self
+    where
+        F: Mockable<I, O>,
+        I: MockArgs + for<'i> PrependInfo<'i>,
+        M: MockInfoFn<I, O> + 'a,
+    {
+        unsafe { self.add(mockable, InfoFnMock(mock), None) }
+    }
+
+    /// Mock must be valid long enough to serve all calls to mocked function during `run`
+    unsafe fn add<I: MockArgs, O, F, M>(
+        mut self,
+        mockable: F,
+        mock: M,
+        times: Option<usize>,
+    ) -> Self
+    where
+        F: Mockable<I, O>,
+        M: DynMock<I, O>,
+    {
+        let fn_name = std::any::type_name::<F>();
+        #[cfg(feature = "registry")]
+        crate::registry::check_registered(fn_name);
+        let mock_box = Box::new(mock) as Box<dyn DynMock<I, O> + '_>;
+        let mock_box_static: Box<dyn DynMock<I, O> + 'static> = std::mem::transmute(mock_box);
+        let report_unused = !self.adding_shared_mocks;
+        self.mock_layer.add(
+            mockable.get_mock_id(),
+            fn_name,
+            mock_box_static,
+            times,
+            report_unused,
+        );
+        self
+    }
+
+    /// Make `run` panic instead of printing a warning when some mocks were never called.
+    ///
+    /// ```should_panic
+    /// use mocktopus::macros::mockable;
+    /// use mocktopus::mocking::{MockContext, MockResult};
+    ///
+    /// #[mockable]
+    /// fn f() -> i32 {
+    ///     0
+    /// }
+    ///
+    /// MockContext::new()
+    ///     .mock_safe(f, || MockResult::Return(1))
+    ///     .deny_unused()
+    ///     .run(|| ()); // panics, `f` was never called
+    /// ```
+    pub fn deny_unused(mut self) -> Self {
+        self.deny_unused = true;
         self
     }
 
+    /// Set up all mockable methods of `R` to be served by a fake `F`.
+    ///
+    /// Annotating a non-generic inherent impl block with `#[mockable(substitute)]` generates a trait
+    /// named after the type with `Api` suffix. The trait has a method for every mockable method
+    /// of the impl block, which takes the same arguments except for the receiver and returns the same type.
+    /// Methods, which are generic, async or return a type with a lifetime, are not included.
+    /// The trait is `pub(crate)`, so fakes can be defined in test modules.
+    ///
+    /// Every included method of `R` is mocked to call the same-named method of the fake.
+    /// All of them share a single fake instance, so its state is preserved between the calls
+    /// for the whole duration of [`run`](#method.run).
+    /// Methods of the fake, which were never called, are not reported as unused mocks.
+    ///
+    /// ```
+    /// use mocktopus::macros::mockable;
+    /// use mocktopus::mocking::MockContext;
+    ///
+    /// struct Repository;
+    ///
+    /// #[mockable(substitute)]
+    /// impl Repository {
+    ///     fn insert(&mut self, item: String) {
+    ///         unimplemented!()
+    ///     }
+    ///
+    ///     fn count(&self) -> usize {
+    ///         unimplemented!()
+    ///     }
+    /// }
+    ///
+    /// #[derive(Default)]
+    /// struct FakeRepository(Vec<String>);
+    ///
+    /// impl RepositoryApi for FakeRepository {
+    ///     fn insert(&mut self, item: String) {
+    ///         self.0.push(item)
+    ///     }
+    ///
+    ///     fn count(&mut self) -> usize {
+    ///         self.0.len()
+    ///     }
+    /// }
+    ///
+    /// MockContext::new()
+    ///     .substitute::<Repository, _>(FakeRepository::default())
+    ///     .run(|| {
+    ///         Repository.insert("item".to_string());
+    ///         assert_eq!(1, Repository.count());
+    ///     });
+    /// ```
+    pub fn substitute<R: Substitutable<F>, F: 'a>(mut self, fake: F) -> Self {
+        self.adding_shared_mocks = true;
+        let mut context = R::substitute(self, SharedFake::new(fake));
+        context.adding_shared_mocks = false;
+        context
+    }
+
+    /// Set up mocks of the set, each one created by its factory.
+    ///
+    /// Mocks of the set, which were never called, are not reported as unused mocks.
+    /// Mocks set up after this call replace mocks of the set for the same functions.
+    ///
+    /// ```
+    /// use mocktopus::macros::mockable;
+    /// use mocktopus::mocking::{MockContext, MockResult, MockSet};
+    ///
+    /// #[mockable]
+    /// fn f() -> i32 {
+    ///     0
+    /// }
+    ///
+    /// #[mockable]
+    /// fn g() -> i32 {
+    ///     0
+    /// }
+    ///
+    /// let defaults = MockSet::new()
+    ///     .mock(f, || || MockResult::Return(1))
+    ///     .mock(g, || || MockResult::Return(2));
+    ///
+    /// MockContext::new()
+    ///     .with(&defaults)
+    ///     .mock_safe(g, || MockResult::Return(3))
+    ///     .run(|| {
+    ///         assert_eq!(1, f());
+    ///         assert_eq!(3, g());
+    ///     });
+    /// ```
+    pub fn with(mut self, set: &MockSet) -> Self {
+        self.adding_shared_mocks = true;
+        let mut context = set
+            .entries
+            .iter()
+            .fold(self, |context, (_, add)| add(context));
+        context.adding_shared_mocks = false;
+        context
+    }
+
     /// Run the function while mocking all the functions.
     ///
     /// This function will mock all functions registered for mocking, run the
@@ -218,17 +1097,321 @@ impl<'a> MockContext<'a> {
     /// thread and other threads may invoke the real implementations.
     ///
     /// Register a function for mocking with [`mock_safe`](#method.mock_safe).
+    /// Mocks set up inside the function with [Mockable](trait.Mockable.html) methods, e.g.
+    /// [Mockable::mock_safe](trait.Mockable.html#tymethod.mock_safe), are set in the context too,
+    /// so they replace its mocks and are removed after the function returns.
+    /// Use [Mockable::mock_thread_wide](trait.Mockable.html#tymethod.mock_thread_wide) to set a mock,
+    /// which stays after that.
+    ///
+    /// # Panics
+    /// Panics after running the function with the [MockError](enum.MockError.html)
+    /// returned by [try_run](#method.try_run).
     pub fn run<T, F: FnOnce() -> T>(self, f: F) -> T {
-        MOCK_STORE.with(|mock_store| unsafe { mock_store.add_layer(self.mock_layer) });
-        let _mock_level_guard = MockLayerGuard;
-        f()
+        match self.try_run(f) {
+            Ok(result) => result,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// Run the function like [run](#method.run), but return mocks, which were never called, as an error.
+    ///
+    /// They fail only if [deny_unused](#method.deny_unused) was set.
+    /// This is the only verification done, e.g. calls of a [mock_times](#method.mock_times) mock
+    /// after its limit are not errors, they just reach the layer below.
+    /// Panics of the function are not caught.
+    ///
+    /// ```
+    /// use mocktopus::macros::mockable;
+    /// use mocktopus::mocking::{MockContext, MockError, MockResult};
+    ///
+    /// #[mockable]
+    /// fn f() -> i32 {
+    ///     0
+    /// }
+    ///
+    /// let result = MockContext::new()
+    ///     .mock_safe(f, || MockResult::Return(1))
+    ///     .deny_unused()
+    ///     .try_run(|| ());
+    ///
+    /// match result {
+    ///     Err(MockError::UnusedMocks { fn_names }) => assert_eq!(1, fn_names.len()),
+    ///     _ => panic!("mock of f should be unused"),
+    /// }
+    /// ```
+    pub fn try_run<T, F: FnOnce() -> T>(self, f: F) -> Result<T, MockError> {
+        let mock_layer_guard = self.enter();
+        let result = f();
+        mock_layer_guard.exit().map(|()| result)
+    }
+
+    /// Adds the mocks to the thread until the returned guard exits or is dropped
+    pub(crate) fn enter(self) -> MockLayerGuard {
+        let deny_unused = self.deny_unused;
+        let key =
+            MOCK_STORE.with(|mock_store| unsafe { mock_store.add_layer(self.mock_layer, None) });
+        MockLayerGuard {
+            key,
+            deny_unused,
+            removed: false,
+        }
+    }
+}
+
+/// Failure of verifying mocks of a [MockContext](struct.MockContext.html),
+/// currently only mocks, which were never called, are verified
+///
+/// Returned by [MockContext::try_run](struct.MockContext.html#method.try_run),
+/// [MockContext::run](struct.MockContext.html#method.run) panics with its `Display`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MockError {
+    /// Mocks were never called during the run
+    UnusedMocks {
+        /// Full paths of the mocked functions, sorted
+        fn_names: Vec<&'static str>,
+    },
+}
+
+impl fmt::Display for MockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MockError::UnusedMocks { fn_names } => write!(
+                f,
+                "MockContext mocks were never called: {}",
+                fn_names.join(", ")
+            ),
+        }
     }
 }
 
-struct MockLayerGuard;
+impl std::error::Error for MockError {}
+
+/// Reusable description of mocks, which are created fresh for every [MockContext](struct.MockContext.html)
+/// set up [with](struct.MockContext.html#method.with) it
+///
+/// Sets are composable, so fixtures shared by many tests can be combined and have single mocks replaced:
+///
+/// ```
+/// #[mock_fixture]
+/// fn defaults() -> MockSet {
+///     MockSet::new()
+///         .mock(get_config, || || MockResult::Return(Config::default()))
+///         .mock(load_user, || |_| MockResult::Return(None))
+/// }
+///
+/// fn with_admin() -> MockSet {
+///     defaults().mock(load_user, || |id| MockResult::Return(Some(User::admin(id))))
+/// }
+///
+/// #[mocktopus::test(fixture(defaults))]
+/// fn anonymous_test() {
+///     assert!(!is_admin(1));
+/// }
+///
+/// #[test]
+/// fn admin_test() {
+///     MockContext::new().with(&with_admin()).run(|| assert!(is_admin(1)));
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct MockSet {
+    entries: Vec<(TypeId, MockSetEntry)>,
+}
+
+/// Sets up a mock created by a factory in a context
+type MockSetEntry = Rc<dyn for<'a> Fn(MockContext<'a>) -> MockContext<'a>>;
+
+impl MockSet {
+    /// Creates an empty set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a mock of the function created by the factory whenever the set is used,
+    /// replacing a previously added mock of the same function
+    pub fn mock<T: MockArgs, O, F, M, C>(mut self, mockable: F, factory: C) -> Self
+    where
+        F: Mockable<T, O> + Clone + 'static,
+        M: MockFn<T, O> + 'static,
+        C: Fn() -> M + 'static,
+    {
+        let id = unsafe { mockable.get_mock_id() };
+        let entry: MockSetEntry =
+            Rc::new(move |context| context.mock_safe(mockable.clone(), factory()));
+        self.insert(id, entry);
+        self
+    }
+
+    /// Adds all mocks of the other set, which replace mocks of the same functions in this set
+    pub fn extend(mut self, other: MockSet) -> Self {
+        for (id, entry) in other.entries {
+            self.insert(id, entry);
+        }
+        self
+    }
+
+    /// Returns true if the set has no mocks
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn insert(&mut self, id: TypeId, entry: MockSetEntry) {
+        match self
+            .entries
+            .iter_mut()
+            .find(|(entry_id, _)| *entry_id == id)
+        {
+            Some((_, replaced)) => *replaced = entry,
+            None => self.entries.push((id, entry)),
+        }
+    }
+}
+
+/// Type, whose mockable methods can be served by a fake `F`
+///
+/// Implemented by `#[mockable]` for types of non-generic inherent impl blocks,
+/// see [`MockContext::substitute`](struct.MockContext.html#method.substitute).
+pub trait Substitutable<F> {
+    /// Sets up the context to route all mockable methods to the shared fake
+    fn substitute<'a>(context: MockContext<'a>, fake: SharedFake<F>) -> MockContext<'a>
+    where
+        F: 'a;
+}
 
-impl<'a> Drop for MockLayerGuard {
+/// Fake shared between all mocks set up by [`MockContext::substitute`](struct.MockContext.html#method.substitute)
+pub struct SharedFake<F> {
+    fake: Rc<RefCell<F>>,
+}
+
+impl<F> SharedFake<F> {
+    fn new(fake: F) -> Self {
+        SharedFake {
+            fake: Rc::new(RefCell::new(fake)),
+        }
+    }
+
+    /// Calls closure with the fake borrowed mutably
+    ///
+    /// # Panics
+    /// Panics if the fake is already borrowed, e.g. when the fake calls its own substituted type.
+    pub fn with<T, W: FnOnce(&mut F) -> T>(&self, with: W) -> T {
+        let mut fake = self
+            .fake
+            .try_borrow_mut()
+            .expect("Fake called while already serving another call");
+        with(&mut fake)
+    }
+}
+
+impl<F> Clone for SharedFake<F> {
+    fn clone(&self) -> Self {
+        SharedFake {
+            fake: self.fake.clone(),
+        }
+    }
+}
+
+/// Removes a mock set with [mock_scoped](trait.Mockable.html#tymethod.mock_scoped) on drop
+struct ScopedMockGuard {
+    handle: MockHandle,
+}
+
+impl Drop for ScopedMockGuard {
+    fn drop(&mut self) {
+        // The store may be already gone if the guard is dropped during thread teardown
+        let _ = MOCK_STORE.try_with(|mock_store| mock_store.remove_pushed(&self.handle));
+    }
+}
+
+/// Marks a scope, in which mocks set up with `mock_raw` are valid
+///
+/// With `debug-checks` feature enabled every mock set up with [Mockable::mock_raw](trait.Mockable.html#tymethod.mock_raw)
+/// or [MockContext::mock_raw](struct.MockContext.html#method.mock_raw) is bound to the innermost living scope.
+/// Calling it after the scope is dropped panics instead of using closure captures, which may be already gone.
+/// Without the feature the scope does nothing.
+///
+/// ```
+/// #[test]
+/// fn get_string_test() {
+///     {
+///         let _scope = MockScope::new();
+///         let mocked = "mocked".to_string();
+///         unsafe {
+///             get_string.mock_raw(|_| MockResult::Return(&mocked));
+///         }
+///
+///         assert_eq!("mocked", get_string(&Context::default()));
+///     }
+///
+///     get_string(&Context::default()); // panics with `debug-checks`
+/// }
+/// ```
+#[must_use = "the scope ends when it's dropped"]
+pub struct MockScope {
+    #[cfg(feature = "debug-checks")]
+    generation: u64,
+    phantom_not_send: PhantomData<*const ()>,
+}
+
+impl MockScope {
+    /// Starts a new scope lasting until the returned value is dropped
+    pub fn new() -> Self {
+        MockScope {
+            #[cfg(feature = "debug-checks")]
+            generation: MOCK_STORE.with(|mock_store| mock_store.open_scope()),
+            phantom_not_send: PhantomData,
+        }
+    }
+}
+
+impl Default for MockScope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for MockScope {
     fn drop(&mut self) {
-        MOCK_STORE.with(|mock_store| unsafe { mock_store.remove_layer() });
+        #[cfg(feature = "debug-checks")]
+        let _ = MOCK_STORE.try_with(|mock_store| mock_store.close_scope(self.generation));
+    }
+}
+
+/// Removes the mock layer of a context on drop, unless it already exited after a successful run
+pub(crate) struct MockLayerGuard {
+    key: u64,
+    deny_unused: bool,
+    removed: bool,
+}
+
+impl MockLayerGuard {
+    /// Removes the layer and verifies its mocks
+    pub(crate) fn exit(mut self) -> Result<(), MockError> {
+        let unused = self.remove();
+        if unused.is_empty() {
+            return Ok(());
+        }
+        let error = MockError::UnusedMocks { fn_names: unused };
+        if self.deny_unused {
+            return Err(error);
+        }
+        eprintln!("Mocktopus warning: {}", error);
+        Ok(())
+    }
+
+    /// Returns sorted names of functions, which mocks were never called
+    fn remove(&mut self) -> Vec<&'static str> {
+        self.removed = true;
+        MOCK_STORE.with(|mock_store| mock_store.remove_layer(self.key))
+    }
+}
+
+impl Drop for MockLayerGuard {
+    fn drop(&mut self) {
+        // Unused mocks are irrelevant when the run already fails
+        if !self.removed {
+            self.remove();
+        }
     }
 }
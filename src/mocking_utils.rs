@@ -1,8 +1,14 @@
+pub use crate::debug_diff::{assert_args_eq, DebugDiff};
+use std::cell::{Cell, RefCell, UnsafeCell};
+
 /// Converts non-mutable reference to a mutable one
 ///
 /// Allows creating multiple mutable references to a single item breaking Rust's safety policy.
 /// # Safety
 /// Use with extreme caution, may cause all sorts of mutability related undefined behaviors!
+/// Tests using it are likely to be rejected by Miri.
+///
+/// Consider using [MockArena](struct.MockArena.html) instead, which is safe.
 ///
 /// One safe use case is when mocking function, which gets called only once during whole test execution, for example:
 ///
@@ -23,6 +29,145 @@
 ///     assert_eq!("mocked", get_string(&mut Context::default()));
 /// }
 /// ```
+#[allow(clippy::mut_from_ref, invalid_reference_casting)]
 pub unsafe fn as_mut<T>(t_ref: &T) -> &mut T {
     &mut *(t_ref as *const T as *mut T)
 }
+
+/// Storage for values referenced by values returned from mocks
+///
+/// Values allocated in arena live as long as the arena, so mocks can return references to them
+/// without leaking memory or breaking mutability rules. Create the arena before
+/// [MockContext](../mocking/struct.MockContext.html), so it outlives all the mocks using it:
+///
+/// ```
+/// #[mockable]
+/// fn get_string(context: &mut Context) -> &mut String {
+///     context.get_mut_string()
+/// }
+///
+/// #[test]
+/// fn get_string_test() {
+///     let arena = MockArena::new();
+///     MockContext::new()
+///         .mock_safe(get_string, |_| MockResult::Return(arena.alloc_mut("mocked".to_string())))
+///         .run(|| {
+///             assert_eq!("mocked", get_string(&mut Context::default()));
+///         });
+/// }
+/// ```
+/// The values are dropped together with the arena.
+#[derive(Default)]
+pub struct MockArena {
+    allocations: RefCell<Vec<Allocation>>,
+}
+
+impl MockArena {
+    /// Creates an empty arena
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Moves value into the arena and returns a shared reference to it
+    pub fn alloc<T: 'static>(&self, value: T) -> &T {
+        unsafe { &*self.alloc_raw(value) }
+    }
+
+    /// Moves value into the arena and returns a mutable reference to it
+    ///
+    /// The value is never accessed by the arena until it's dropped, so the reference is unique.
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_mut<T: 'static>(&self, value: T) -> &mut T {
+        unsafe { &mut *self.alloc_raw(value) }
+    }
+
+    /// Moves value into the arena and returns a slot, which can hand out references to it many times
+    ///
+    /// The slot checks at runtime, that either a single mutable reference or any number of shared references
+    /// is handed out, so it's usable for mocks called multiple times.
+    pub fn slot<T: 'static>(&self, value: T) -> ArenaSlot<'_, T> {
+        let slot = SlotData {
+            borrow: Cell::new(SlotBorrow::Unborrowed),
+            value: UnsafeCell::new(value),
+        };
+        ArenaSlot {
+            slot: unsafe { &*self.alloc_raw(slot) },
+        }
+    }
+
+    fn alloc_raw<T: 'static>(&self, value: T) -> *mut T {
+        let ptr = Box::into_raw(Box::new(value));
+        self.allocations.borrow_mut().push(Allocation {
+            ptr: ptr as *mut (),
+            drop: drop_allocation::<T>,
+        });
+        ptr
+    }
+}
+
+impl Drop for MockArena {
+    fn drop(&mut self) {
+        for allocation in self.allocations.get_mut().drain(..) {
+            unsafe { (allocation.drop)(allocation.ptr) }
+        }
+    }
+}
+
+struct Allocation {
+    ptr: *mut (),
+    drop: unsafe fn(*mut ()),
+}
+
+unsafe fn drop_allocation<T>(ptr: *mut ()) {
+    drop(Box::from_raw(ptr as *mut T))
+}
+
+/// Value in [MockArena](struct.MockArena.html), which hands out references with runtime borrow checking
+pub struct ArenaSlot<'a, T> {
+    slot: &'a SlotData<T>,
+}
+
+impl<'a, T> ArenaSlot<'a, T> {
+    /// Returns a shared reference to the value
+    /// # Panics
+    /// If mutable reference was already handed out
+    pub fn get(&self) -> &'a T {
+        match self.slot.borrow.get() {
+            SlotBorrow::Mutable => panic!("Arena slot value already borrowed mutably"),
+            _ => self.slot.borrow.set(SlotBorrow::Shared),
+        }
+        unsafe { &*self.slot.value.get() }
+    }
+
+    /// Returns the only mutable reference to the value
+    /// # Panics
+    /// If any reference was already handed out
+    #[allow(clippy::mut_from_ref)]
+    pub fn get_mut(&self) -> &'a mut T {
+        match self.slot.borrow.get() {
+            SlotBorrow::Unborrowed => self.slot.borrow.set(SlotBorrow::Mutable),
+            _ => panic!("Arena slot value already borrowed"),
+        }
+        unsafe { &mut *self.slot.value.get() }
+    }
+}
+
+impl<T> Clone for ArenaSlot<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ArenaSlot<'_, T> {}
+
+struct SlotData<T> {
+    borrow: Cell<SlotBorrow>,
+    value: UnsafeCell<T>,
+}
+
+#[derive(Clone, Copy)]
+enum SlotBorrow {
+    Unborrowed,
+    Shared,
+    Mutable,
+}
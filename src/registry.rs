@@ -0,0 +1,210 @@
+use std::fmt;
+
+/// Mockable function listed in the registry returned by [registry](fn.registry.html)
+#[derive(Debug)]
+pub struct RegisteredFn {
+    module_path: &'static str,
+    self_ty: Option<&'static str>,
+    trait_name: Option<&'static str>,
+    name: &'static str,
+    signature: &'static str,
+    file: &'static str,
+    line: u32,
+    column: u32,
+}
+
+inventory::collect!(RegisteredFn);
+
+impl RegisteredFn {
+    #[doc(hidden)]
+    #[allow(clippy::too_many_arguments)]
+    pub const fn new(
+        module_path: &'static str,
+        self_ty: Option<&'static str>,
+        trait_name: Option<&'static str>,
+        name: &'static str,
+        signature: &'static str,
+        file: &'static str,
+        line: u32,
+        column: u32,
+    ) -> Self {
+        RegisteredFn {
+            module_path,
+            self_ty,
+            trait_name,
+            name,
+            signature,
+            file,
+            line,
+            column,
+        }
+    }
+
+    /// Path of the function, e.g. `crate::module::Type::function` or `crate::module::<Type as Trait>::function`.
+    ///
+    /// Only the last segment of the type and the trait is included.
+    pub fn path(&self) -> String {
+        match (self.self_ty, self.trait_name) {
+            (None, None) => format!("{}::{}", self.module_path, self.name),
+            (Some(self_ty), None) => format!("{}::{}::{}", self.module_path, self_ty, self.name),
+            (None, Some(trait_name)) => {
+                format!("{}::{}::{}", self.module_path, trait_name, self.name)
+            }
+            (Some(self_ty), Some(trait_name)) => format!(
+                "{}::<{} as {}>::{}",
+                self.module_path, self_ty, trait_name, self.name
+            ),
+        }
+    }
+
+    /// Module, where the function is defined, as returned by `module_path!`
+    pub fn module_path(&self) -> &'static str {
+        self.module_path
+    }
+
+    /// Name of the function
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Signature of the function as written in the source code
+    pub fn signature(&self) -> &'static str {
+        self.signature
+    }
+
+    /// Source file, where the function is defined, as returned by `file!`
+    pub fn file(&self) -> &'static str {
+        self.file
+    }
+
+    /// Line of the function name in the source file
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    /// Column of the function name in the source file
+    pub fn column(&self) -> u32 {
+        self.column
+    }
+
+    /// Checks if the function has the given name as returned by `std::any::type_name`
+    fn matches(&self, fn_name: &ParsedFnName) -> bool {
+        if self.name != fn_name.name {
+            return false;
+        }
+        let self_ty_matches = |self_ty: &str| fn_name.self_ty.as_deref() == Some(self_ty);
+        match (self.self_ty, self.trait_name) {
+            (None, None) => {
+                fn_name.trait_name.is_none()
+                    && fn_name
+                        .path
+                        .strip_prefix(self.module_path)
+                        .is_some_and(|rest| rest.starts_with("::"))
+            }
+            (Some(self_ty), None) => fn_name.trait_name.is_none() && self_ty_matches(self_ty),
+            (self_ty, Some(trait_name)) => {
+                fn_name.trait_name.as_deref() == Some(trait_name)
+                    && self_ty.is_none_or(self_ty_matches)
+            }
+        }
+    }
+}
+
+impl fmt::Display for RegisteredFn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at {}:{}:{}",
+            self.path(),
+            self.file,
+            self.line,
+            self.column
+        )
+    }
+}
+
+/// Lists all mockable functions linked into the program
+///
+/// Available with `registry` feature enabled, which registers every function made mockable with `#[mockable]`.
+pub fn registry() -> impl Iterator<Item = &'static RegisteredFn> {
+    inventory::iter::<RegisteredFn>.into_iter()
+}
+
+/// Warns in debug builds if the mocked function isn't in the registry
+pub fn check_registered(fn_name: &str) {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+    let parsed = ParsedFnName::parse(fn_name);
+    if registry().any(|registered| registered.matches(&parsed)) {
+        return;
+    }
+    eprintln!(
+        "Mocktopus warning: mocked function {} is not in the registry of mockable functions, \
+         it must be annotated with #[mockable] for the mock to have any effect",
+        fn_name
+    );
+}
+
+/// Parts of a function name as returned by `std::any::type_name` needed for finding it in the registry
+struct ParsedFnName {
+    /// Path with generic arguments removed
+    path: String,
+    name: String,
+    /// Last segment of the path of `Self` type or the segment preceding name if path isn't qualified
+    self_ty: Option<String>,
+    trait_name: Option<String>,
+}
+
+impl ParsedFnName {
+    fn parse(fn_name: &str) -> Self {
+        let path = strip_generic_args(fn_name);
+        // Qualified path `<Type as Trait>::name`
+        if let Some(qualified) = path.strip_prefix('<') {
+            if let Some((self_ty, rest)) = qualified.split_once(" as ") {
+                if let Some((trait_name, name)) = rest.rsplit_once(">::") {
+                    return ParsedFnName {
+                        name: name.to_string(),
+                        self_ty: Some(last_ident(self_ty).to_string()),
+                        trait_name: Some(last_ident(trait_name).to_string()),
+                        path,
+                    };
+                }
+            }
+        }
+        let (prefix, name) = path.rsplit_once("::").unwrap_or(("", &path));
+        ParsedFnName {
+            name: name.to_string(),
+            self_ty: Some(last_ident(prefix).to_string()),
+            trait_name: None,
+            path,
+        }
+    }
+}
+
+fn strip_generic_args(fn_name: &str) -> String {
+    let mut stripped = String::with_capacity(fn_name.len());
+    let mut depth = 0;
+    let mut prev = ' ';
+    for c in fn_name.chars() {
+        match c {
+            '<' if depth > 0 || is_ident_char(prev) => depth += 1,
+            // Arrow of a function pointer type isn't a closing bracket
+            '>' if depth > 0 && prev != '-' => depth -= 1,
+            _ if depth > 0 => (),
+            _ => stripped.push(c),
+        }
+        prev = c;
+    }
+    stripped
+}
+
+/// Last identifier in a path, which may end with a bracket, e.g. in `module::<impl module::Type>`
+fn last_ident(path: &str) -> &str {
+    let path = path.trim_end_matches(|c| !is_ident_char(c));
+    path.rsplit(|c| !is_ident_char(c)).next().unwrap_or(path)
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
@@ -1,4 +1,7 @@
-#![feature(proc_macro_hygiene)]
+#![cfg_attr(feature = "nightly", feature(proc_macro_hygiene))]
+#![allow(clippy::module_inception, dead_code)]
+// Fixtures of skipped items trigger the deprecation warnings, which `warn-skipped` emits on purpose
+#![cfg_attr(feature = "warn-skipped", allow(deprecated))]
 
 // Test if injecting works even if mocktopus is aliased
 extern crate mocktopus as mocktopus_aliased;
@@ -79,6 +82,8 @@ mod injector_injects_annotated_traits {
     }
 }
 
+// Attributes on file modules are unstable
+#[cfg(feature = "nightly")]
 #[mockable]
 mod module_annotated_declaration;
 
@@ -192,6 +197,7 @@ mod injector_injects_annotated_items {
         }
     }
 
+    #[cfg(feature = "nightly")]
     mod does_not_inject_mod_with_annotated_declaration {
         use super::*;
 
@@ -620,6 +626,130 @@ mod injector_ignores_unsafe_impls {
     // Trait Mockable is not implemented for unsafe functions
 }
 
+mod injector_does_not_generate_api_trait_without_substitute_argument {
+    use super::*;
+
+    struct Struct;
+
+    trait StructApi {
+        fn function(&self) -> &'static str {
+            "not mocked"
+        }
+    }
+
+    impl StructApi for Struct {}
+
+    #[mockable]
+    impl Struct {
+        fn function() -> &'static str {
+            "not mocked"
+        }
+    }
+
+    #[test]
+    fn when_not_mocked_then_runs_normally() {
+        assert_eq!("not mocked", Struct::function());
+        assert_eq!("not mocked", StructApi::function(&Struct));
+    }
+}
+
+mod injecting_many_impl_blocks_of_struct {
+    use super::*;
+
+    struct Struct;
+
+    #[mockable]
+    impl Struct {
+        fn first() -> &'static str {
+            "not mocked"
+        }
+    }
+
+    #[mockable]
+    impl Struct {
+        fn second() -> &'static str {
+            "not mocked"
+        }
+    }
+
+    #[test]
+    fn when_not_mocked_then_runs_normally() {
+        assert_eq!("not mocked", Struct::first());
+        assert_eq!("not mocked", Struct::second());
+    }
+
+    #[test]
+    fn when_mocked_then_returns_mock() {
+        Struct::first.mock_safe(|| MockResult::Return("mocked"));
+        Struct::second.mock_safe(|| MockResult::Return("mocked"));
+
+        assert_eq!("mocked", Struct::first());
+        assert_eq!("mocked", Struct::second());
+    }
+}
+
+mod injecting_cfg_alternatives {
+    use super::*;
+
+    #[mockable]
+    mod platform {
+        #[cfg(unix)]
+        pub fn name() -> &'static str {
+            "not mocked"
+        }
+
+        #[cfg(not(unix))]
+        pub fn name() -> &'static str {
+            "not mocked"
+        }
+
+        pub struct Struct;
+
+        impl Struct {
+            #[cfg(unix)]
+            pub fn name(&self) -> &'static str {
+                "not mocked"
+            }
+
+            #[cfg(not(unix))]
+            pub fn name(&self) -> &'static str {
+                "not mocked"
+            }
+        }
+    }
+
+    #[test]
+    fn when_mocked_by_name_then_returns_mock() {
+        platform::name.mock_named(|_: platform::name_args| MockResult::Return("mocked"));
+        platform::Struct::name
+            .mock_named(|_: platform::Struct_name_args| MockResult::Return("mocked"));
+
+        assert_eq!("mocked", platform::name());
+        assert_eq!("mocked", platform::Struct.name());
+    }
+}
+
+mod injecting_const_generic_fns {
+    use super::*;
+
+    #[mockable]
+    fn sum<const N: usize>(a: [u8; N]) -> u8 {
+        a.iter().sum()
+    }
+
+    #[test]
+    fn when_not_mocked_then_runs_normally() {
+        assert_eq!(3, sum([1, 2]));
+    }
+
+    #[test]
+    fn when_mocked_then_returns_mock() {
+        sum::<2>.mock_safe(|_: [u8; 2]| MockResult::Return(0));
+
+        assert_eq!(0, sum([1, 2]));
+    }
+}
+
 mod injector_does_not_inject_macro_generated_fns {
     use super::*;
 
@@ -649,6 +779,71 @@ mod injector_does_not_inject_macro_generated_fns {
     }
 }
 
+mod injecting_fns_returning_never {
+    use super::*;
+
+    #[mockable]
+    fn function() -> ! {
+        panic!("not mocked")
+    }
+
+    #[test]
+    #[should_panic(expected = "not mocked")]
+    fn when_not_mocked_then_runs_normally() {
+        function();
+    }
+
+    #[test]
+    #[should_panic(expected = "MockResult::ContinueThen can't be used")]
+    fn when_mocked_with_continue_then_then_panics() {
+        function.mock_safe(|| MockResult::continue_then((), |result| result));
+
+        function();
+    }
+}
+
+mod injecting_track_caller_fns {
+    use super::*;
+    use std::panic::Location;
+
+    #[mockable]
+    #[track_caller]
+    fn function() -> u32 {
+        Location::caller().line()
+    }
+
+    #[test]
+    fn when_not_mocked_then_reports_caller_location() {
+        let (line, caller_line) = (line!(), function());
+
+        assert_eq!(line, caller_line);
+    }
+
+    #[test]
+    fn when_mocked_with_continue_then_reports_caller_location() {
+        function.mock_safe(|| MockResult::Continue(()));
+
+        let (line, caller_line) = (line!(), function());
+
+        assert_eq!(line, caller_line);
+    }
+
+    #[test]
+    fn when_mocked_then_returns_mock() {
+        function.mock_safe(|| MockResult::Return(0));
+
+        assert_eq!(0, function());
+    }
+
+    #[test]
+    #[should_panic(expected = "MockResult::ContinueThen can't be used")]
+    fn when_mocked_with_continue_then_then_panics() {
+        function.mock_safe(|| MockResult::continue_then((), |result| result));
+
+        function();
+    }
+}
+
 mod injector_unignores_args {
     use super::*;
 
@@ -1,3 +1,9 @@
+#![cfg(feature = "nightly")]
+#![allow(
+    clippy::needless_borrows_for_generic_args,
+    clippy::multiple_bound_locations
+)]
+
 extern crate mocktopus;
 
 mod mocking_fns;
@@ -362,7 +368,7 @@ mod clear_mocks {
                 assert_eq!("mocked 1 context", mockable_1());
                 clear_mocks();
                 assert_eq!("not mocked 1", mockable_1());
-                mockable_1.mock_safe(|| MockResult::Return("mocked 1 post"));
+                mockable_1.mock_thread_wide(|| MockResult::Return("mocked 1 post"));
                 assert_eq!("mocked 1 post", mockable_1());
             });
         assert_eq!("mocked 1 post", mockable_1());
@@ -517,4 +523,1720 @@ mod mock_context {
             });
         assert_eq!("not mocked, mocked", mockable_string());
     }
+
+    #[test]
+    fn mocks_set_inside_run_closure_replace_context_mocks_only_inside_run_closure() {
+        mockable_1.mock_safe(|| MockResult::Return("mocked 1"));
+        MockContext::new()
+            .mock_safe(mockable_1, || MockResult::Return("mocked 1 context"))
+            .run(|| {
+                mockable_1.mock_safe(|| MockResult::Return("mocked 1 inside"));
+                mockable_2.mock_safe(|| MockResult::Return("mocked 2 inside"));
+
+                assert_eq!("mocked 1 inside", mockable_1());
+                assert_eq!("mocked 2 inside", mockable_2());
+            });
+        assert_eq!("mocked 1", mockable_1());
+        assert_eq!("not mocked 2", mockable_2());
+    }
+
+    #[test]
+    fn mocks_set_inside_nested_run_closure_are_set_in_innermost_context() {
+        MockContext::new()
+            .mock_safe(mockable_1, || MockResult::Return("mocked 1 context 1"))
+            .run(|| {
+                MockContext::new().run(|| {
+                    mockable_1.mock_safe(|| MockResult::Return("mocked 1 context 2"));
+
+                    assert_eq!("mocked 1 context 2", mockable_1());
+                });
+                assert_eq!("mocked 1 context 1", mockable_1());
+            });
+        assert_eq!("not mocked 1", mockable_1());
+    }
+
+    #[test]
+    fn mocks_of_all_kinds_set_inside_run_closure_replace_context_mocks_and_are_removed_with_it() {
+        let mocked = "mocked 2 scoped".to_string();
+        MockContext::new()
+            .mock_safe(mockable_1, || MockResult::Return("mocked 1 context"))
+            .run(|| {
+                mockable_1.mock_once(|| MockResult::Return("mocked 1 once"));
+                mockable_string.mock_scoped(
+                    || MockResult::Return(mocked.clone()),
+                    || {
+                        assert_eq!("mocked 1 once", mockable_1());
+                        assert_eq!("not mocked 1", mockable_1());
+                        assert_eq!("mocked 2 scoped", mockable_string());
+                        let active_mocks = active_mocks();
+                        assert!(active_mocks.layers()[0].fn_names().is_empty());
+                        assert_eq!(1, active_mocks.layers()[1].fn_names().len());
+                    },
+                );
+            });
+        assert!(!mockable_1.is_mocked());
+        assert!(!mockable_string.is_mocked());
+    }
+
+    #[test]
+    fn thread_wide_mocks_set_inside_run_closure_are_shadowed_by_context_mocks_and_stay_after_it() {
+        MockContext::new()
+            .mock_safe(mockable_1, || MockResult::Return("mocked 1 context"))
+            .run(|| {
+                mockable_1.mock_thread_wide(|| MockResult::Return("mocked 1 thread"));
+
+                assert_eq!("mocked 1 context", mockable_1());
+            });
+        assert_eq!("mocked 1 thread", mockable_1());
+    }
+}
+
+mod substitute {
+    use super::*;
+    use repository::{Repository, RepositoryApi};
+
+    // Fake is defined outside of the impl block module like in a separate test module
+    mod repository {
+        use super::*;
+
+        pub struct Repository {
+            pub items: Vec<String>,
+        }
+
+        #[mockable(substitute)]
+        impl Repository {
+            pub fn new() -> Self {
+                Repository { items: Vec::new() }
+            }
+
+            pub fn insert(&mut self, item: String) {
+                self.items.push(item)
+            }
+
+            pub fn count(&self) -> usize {
+                self.items.len()
+            }
+
+            pub fn first(&self) -> Option<&String> {
+                self.items.first()
+            }
+
+            pub fn describe<T: Display>(&self, prefix: T) -> String {
+                format!("{} {}", prefix, self.items.len())
+            }
+        }
+    }
+
+    #[derive(Default)]
+    struct FakeRepository {
+        inserted: Vec<String>,
+    }
+
+    impl RepositoryApi for FakeRepository {
+        fn new(&mut self) -> Repository {
+            Repository {
+                items: vec!["created by fake".to_string()],
+            }
+        }
+
+        fn insert(&mut self, item: String) {
+            self.inserted.push(item)
+        }
+
+        fn count(&mut self) -> usize {
+            100 + self.inserted.len()
+        }
+    }
+
+    #[test]
+    fn when_substituted_then_methods_share_fake_state_only_inside_run_closure() {
+        let mut repository = Repository::new();
+        MockContext::new()
+            .substitute::<Repository, _>(FakeRepository::default())
+            .run(|| {
+                repository.insert("a".to_string());
+                repository.insert("b".to_string());
+
+                assert_eq!(102, repository.count());
+                assert_eq!(
+                    Some(&"created by fake".to_string()),
+                    Repository::new().first()
+                );
+            });
+        assert_eq!(0, repository.count());
+        assert_eq!(0, Repository::new().count());
+    }
+
+    #[test]
+    fn when_substituted_then_methods_not_included_in_api_run_normally() {
+        let repository = Repository {
+            items: vec!["real".to_string()],
+        };
+        MockContext::new()
+            .substitute::<Repository, _>(FakeRepository::default())
+            .run(|| {
+                assert_eq!(Some(&"real".to_string()), repository.first());
+                assert_eq!("items 1", repository.describe("items"));
+            });
+    }
+
+    #[test]
+    fn when_substituted_then_mocks_set_later_in_context_take_precedence() {
+        MockContext::new()
+            .substitute::<Repository, _>(FakeRepository::default())
+            .mock_safe(Repository::count, |_| MockResult::Return(7))
+            .run(|| {
+                assert_eq!(7, Repository::new().count());
+            });
+    }
+}
+
+mod mocks_builder {
+    use super::*;
+    use connection::{Connection, ConnectionMocks};
+
+    // Builder is used outside of the impl block module like in a separate test module
+    mod connection {
+        use super::*;
+
+        pub struct Connection {
+            address: String,
+        }
+
+        #[mockable(mocks)]
+        impl Connection {
+            pub fn new(address: &str) -> Self {
+                Connection {
+                    address: address.to_string(),
+                }
+            }
+
+            pub fn send(&self, prefix: &str, buffer: &[u8]) -> usize {
+                prefix.len() + buffer.len()
+            }
+
+            pub fn describe<T: Display>(&self, value: T) -> String {
+                format!("{} {}", self.address, value)
+            }
+        }
+    }
+
+    #[test]
+    fn when_mocked_with_builder_then_mocks_only_inside_run_closure() {
+        let connection = Connection::new("real");
+        ConnectionMocks::new()
+            .send(|_, prefix, buffer| MockResult::Return(prefix.len() * buffer.len()))
+            .describe::<u32, _>(|connection, value| MockResult::Continue((connection, value + 1)))
+            .run(|| {
+                assert_eq!(6, connection.send("abc", &[1, 2]));
+                assert_eq!("real 2", connection.describe(1u32));
+                assert_eq!("real a", connection.describe("a"));
+            });
+        assert_eq!(5, connection.send("abc", &[1, 2]));
+        assert_eq!("real 1", connection.describe(1u32));
+    }
+
+    #[test]
+    fn when_method_name_clashes_with_builder_method_then_setter_is_prefixed() {
+        ConnectionMocks::new()
+            .mock_new(|_| MockResult::Continue(("mocked",)))
+            .run(|| {
+                assert_eq!("mocked 1", Connection::new("real").describe(1));
+            });
+    }
+
+    #[test]
+    fn when_converted_into_context_then_can_be_combined_with_other_mocks() {
+        let context: MockContext = ConnectionMocks::new()
+            .send(|_, _, _| MockResult::Return(0))
+            .into();
+        ConnectionMocks::from(context.mock_safe(Connection::describe::<&str>, |_, _| {
+            MockResult::Return("mocked".to_string())
+        }))
+        .into_context()
+        .run(|| {
+            let connection = Connection::new("real");
+            assert_eq!(0, connection.send("abc", &[1, 2]));
+            assert_eq!("mocked", connection.describe("a"));
+        });
+    }
+}
+
+mod mock_named {
+    use super::*;
+
+    #[mockable]
+    fn connect(host: &str, port: u16, timeout: u32) -> String {
+        format!("{}:{} {}", host, port, timeout)
+    }
+
+    #[mockable]
+    fn no_args() -> u32 {
+        1
+    }
+
+    struct Server(u16);
+
+    #[mockable]
+    impl Server {
+        fn port(&self, offset: u16) -> u16 {
+            self.0 + offset
+        }
+    }
+
+    #[test]
+    fn when_mocked_with_named_args_then_fields_are_named_after_arguments() {
+        connect.mock_named(|args: connect_args| match args.timeout {
+            0 => MockResult::Return("timeout".to_string()),
+            _ => MockResult::Continue(args),
+        });
+
+        assert_eq!("timeout", connect("localhost", 80, 0));
+        assert_eq!("localhost:80 5", connect("localhost", 80, 5));
+    }
+
+    #[test]
+    fn when_mocked_with_named_args_then_modified_args_are_continued() {
+        connect.mock_named(|mut args: connect_args| {
+            args.port = 8080;
+            MockResult::Continue(args)
+        });
+
+        assert_eq!("localhost:8080 5", connect("localhost", 80, 5));
+    }
+
+    #[test]
+    fn when_function_has_no_args_then_args_struct_is_empty() {
+        no_args.mock_named(|_: no_args_args| MockResult::Return(2));
+
+        assert_eq!(2, no_args());
+    }
+
+    #[test]
+    fn when_method_mocked_with_named_args_then_receiver_is_in_self_field() {
+        Server::port.mock_named(|args: Server_port_args| match args.self_.0 {
+            0 => MockResult::Return(args.offset),
+            _ => MockResult::Continue(args),
+        });
+
+        assert_eq!(3, Server(0).port(3));
+        assert_eq!(4, Server(1).port(3));
+    }
+}
+
+mod continue_then {
+    use super::*;
+    use std::cell::RefCell;
+    use std::num::ParseIntError;
+    use std::rc::Rc;
+
+    #[mockable]
+    fn parse(input: &str) -> Result<Vec<u32>, ParseIntError> {
+        let mut parsed = Vec::new();
+        for item in input.split(',') {
+            parsed.push(item.parse()?);
+        }
+        Ok(parsed)
+    }
+
+    struct Counter(u32);
+
+    #[mockable]
+    impl Counter {
+        fn value_mut(&mut self) -> &mut u32 {
+            &mut self.0
+        }
+    }
+
+    #[test]
+    fn when_continued_then_hook_transforms_real_result() {
+        parse.mock_safe(|input| {
+            MockResult::continue_then((input,), |parsed: Result<Vec<u32>, _>| {
+                parsed.map(|mut parsed| {
+                    parsed[0] = 0;
+                    parsed
+                })
+            })
+        });
+
+        assert_eq!(Ok(vec![0, 2]), parse("1,2"));
+        assert!(parse("1,x").is_err());
+    }
+
+    #[test]
+    fn when_continued_then_hook_receives_real_result_computed_from_modified_args() {
+        let spied = Rc::new(RefCell::new(Vec::new()));
+        let spied_clone = spied.clone();
+        parse.mock_safe(move |_| {
+            let spied = spied_clone.clone();
+            MockResult::continue_then(("3,4",), move |parsed: Result<Vec<u32>, _>| {
+                spied.borrow_mut().push(parsed.clone());
+                parsed
+            })
+        });
+
+        assert_eq!(Ok(vec![3, 4]), parse("1,2"));
+        assert_eq!(vec![Ok(vec![3, 4])], *spied.borrow());
+    }
+
+    #[test]
+    fn when_method_returns_reference_then_hook_can_modify_referenced_value() {
+        Counter::value_mut.mock_safe(|counter| {
+            MockResult::continue_then((counter,), |value: &mut u32| {
+                *value += 1;
+                value
+            })
+        });
+        let mut counter = Counter(1);
+
+        *counter.value_mut() += 10;
+
+        assert_eq!(12, counter.0);
+    }
+}
+
+mod mock_once_and_times {
+    use super::*;
+
+    #[mockable]
+    fn mockable_string() -> String {
+        "not mocked".to_string()
+    }
+
+    #[test]
+    fn when_mocked_once_then_moves_value_out_and_expires() {
+        let mocked = "mocked".to_string();
+        mockable_string.mock_once(move || MockResult::Return(mocked));
+
+        assert_eq!("mocked", mockable_string());
+        assert_eq!("not mocked", mockable_string());
+    }
+
+    #[test]
+    fn when_mocked_times_then_expires_after_given_number_of_calls() {
+        mockable_string.mock_times(2, || MockResult::Return("mocked".to_string()));
+
+        assert_eq!("mocked", mockable_string());
+        assert_eq!("mocked", mockable_string());
+        assert_eq!("not mocked", mockable_string());
+    }
+
+    #[test]
+    fn when_mocked_zero_times_then_runs_normally() {
+        mockable_string.mock_times(0, || MockResult::Return("mocked".to_string()));
+
+        assert_eq!("not mocked", mockable_string());
+    }
+
+    #[test]
+    fn when_context_mock_expires_then_calls_reach_next_layer() {
+        mockable_string.mock_safe(|| MockResult::Return("thread".to_string()));
+        let mocked = "context".to_string();
+
+        MockContext::new()
+            .mock_once(mockable_string, move || MockResult::Return(mocked))
+            .run(|| {
+                assert_eq!("context", mockable_string());
+                assert_eq!("thread", mockable_string());
+            });
+    }
+
+    #[test]
+    fn when_context_mocked_times_then_expires_only_inside_its_run() {
+        let context = MockContext::new().mock_times(mockable_string, 1, || {
+            MockResult::Return("mocked".to_string())
+        });
+
+        assert_eq!("not mocked", mockable_string());
+        context.run(|| {
+            assert_eq!("mocked", mockable_string());
+            assert_eq!("not mocked", mockable_string());
+        });
+    }
+}
+
+mod mock_scoped {
+    use super::*;
+
+    #[mockable]
+    fn mockable_string() -> String {
+        "not mocked".to_string()
+    }
+
+    #[test]
+    fn when_mocked_scoped_then_mocks_only_while_scope_runs() {
+        let mocked = "mocked".to_string();
+
+        let result =
+            mockable_string.mock_scoped(|| MockResult::Return(mocked.clone()), mockable_string);
+
+        assert_eq!("mocked", result);
+        assert_eq!("not mocked", mockable_string());
+    }
+
+    #[test]
+    fn when_scope_ends_then_shadowed_mock_is_restored() {
+        mockable_string.mock_safe(|| MockResult::Return("thread".to_string()));
+
+        mockable_string.mock_scoped(
+            || MockResult::Return("scoped".to_string()),
+            || assert_eq!("scoped", mockable_string()),
+        );
+
+        assert_eq!("thread", mockable_string());
+    }
+
+    #[test]
+    fn when_mocked_scoped_in_nested_scopes_then_each_scope_removes_its_own_mock() {
+        mockable_string.mock_scoped(
+            || MockResult::Return("scoped 1".to_string()),
+            || {
+                mockable_string.mock_scoped(
+                    || MockResult::Return("scoped 2".to_string()),
+                    || assert_eq!("scoped 2", mockable_string()),
+                );
+
+                assert_eq!("scoped 1", mockable_string());
+            },
+        );
+
+        assert_eq!("not mocked", mockable_string());
+    }
+
+    #[test]
+    fn when_mocked_safe_in_scope_then_scope_end_keeps_new_mock() {
+        mockable_string.mock_scoped(
+            || MockResult::Return("scoped".to_string()),
+            || mockable_string.mock_safe(|| MockResult::Return("thread".to_string())),
+        );
+
+        assert_eq!("thread", mockable_string());
+    }
+
+    #[test]
+    fn when_scope_panics_then_mock_is_removed() {
+        let mocked = "mocked".to_string();
+
+        let result = std::panic::catch_unwind(|| {
+            mockable_string.mock_scoped(|| MockResult::Return(mocked.clone()), || panic!())
+        });
+
+        assert!(result.is_err());
+        assert!(!mockable_string.is_mocked());
+    }
+
+    #[test]
+    fn when_mocked_scoped_then_context_mocks_take_precedence() {
+        mockable_string.mock_scoped(
+            || MockResult::Return("scoped".to_string()),
+            || {
+                MockContext::new()
+                    .mock_safe(mockable_string, || {
+                        MockResult::Return("context".to_string())
+                    })
+                    .run(|| assert_eq!("context", mockable_string()));
+                assert_eq!("scoped", mockable_string());
+            },
+        );
+    }
+}
+
+mod mock_arena {
+    use super::*;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::rc::Rc;
+
+    #[derive(Default)]
+    struct Config(String);
+
+    #[mockable]
+    impl Config {
+        fn name(&self) -> &String {
+            &self.0
+        }
+
+        fn name_mut(&mut self) -> &mut String {
+            &mut self.0
+        }
+    }
+
+    #[test]
+    fn when_mock_returns_value_allocated_in_arena_then_reference_is_valid() {
+        let arena = MockArena::new();
+        MockContext::new()
+            .mock_safe(Config::name, |_| {
+                MockResult::Return(arena.alloc("mocked".to_string()))
+            })
+            .run(|| {
+                assert_eq!("mocked", Config::default().name());
+                assert_eq!("mocked", Config::default().name());
+            });
+    }
+
+    #[test]
+    fn when_mock_returns_mutable_value_allocated_in_arena_then_it_can_be_modified() {
+        let arena = MockArena::new();
+        MockContext::new()
+            .mock_safe(Config::name_mut, |_| {
+                MockResult::Return(arena.alloc_mut("mocked".to_string()))
+            })
+            .run(|| {
+                let mut config = Config::default();
+                let name = config.name_mut();
+                name.push('!');
+
+                assert_eq!("mocked!", name);
+            });
+    }
+
+    #[test]
+    fn when_slot_borrowed_mutably_twice_then_panics() {
+        let arena = MockArena::new();
+        let slot = arena.slot("mocked".to_string());
+        MockContext::new()
+            .mock_safe(Config::name_mut, move |_| {
+                MockResult::Return(slot.get_mut())
+            })
+            .run(|| {
+                assert_eq!("mocked", Config::default().name_mut());
+
+                let result = catch_unwind(AssertUnwindSafe(|| Config::default().name_mut().len()));
+
+                assert!(result.is_err());
+            });
+    }
+
+    #[test]
+    fn when_slot_borrowed_shared_many_times_then_returns_same_value() {
+        let arena = MockArena::new();
+        let slot = arena.slot("mocked".to_string());
+        MockContext::new()
+            .mock_safe(Config::name, move |_| MockResult::Return(slot.get()))
+            .run(|| {
+                assert!(std::ptr::eq(
+                    Config::default().name(),
+                    Config::default().name()
+                ));
+            });
+    }
+
+    #[test]
+    fn when_arena_dropped_then_values_are_dropped() {
+        let value = Rc::new(());
+        let arena = MockArena::new();
+        arena.alloc(value.clone());
+        arena.slot(value.clone());
+
+        assert_eq!(3, Rc::strong_count(&value));
+
+        drop(arena);
+
+        assert_eq!(1, Rc::strong_count(&value));
+    }
+}
+
+mod mock_scope {
+    use super::*;
+
+    #[mockable]
+    fn mockable_string() -> String {
+        "not mocked".to_string()
+    }
+
+    #[test]
+    fn when_mocked_raw_inside_scope_then_mocks_while_scope_lives() {
+        let _scope = MockScope::new();
+        let mocked = "mocked".to_string();
+        unsafe {
+            mockable_string.mock_raw(|| MockResult::Return(mocked.clone()));
+        }
+
+        assert_eq!("mocked", mockable_string());
+    }
+
+    #[test]
+    fn when_mocked_safe_inside_scope_then_mocks_after_scope_ends() {
+        {
+            let _scope = MockScope::new();
+            mockable_string.mock_safe(|| MockResult::Return("mocked".to_string()));
+        }
+
+        assert_eq!("mocked", mockable_string());
+    }
+
+    #[test]
+    fn when_mocked_raw_outside_of_scope_then_mocks_after_scope_ends() {
+        unsafe {
+            mockable_string.mock_raw(|| MockResult::Return("mocked".to_string()));
+        }
+        drop(MockScope::new());
+
+        assert_eq!("mocked", mockable_string());
+    }
+
+    #[cfg(feature = "debug-checks")]
+    #[test]
+    #[should_panic(expected = "Mock set up with mock_raw called after its MockScope ended")]
+    fn when_mocked_raw_inside_scope_then_panics_after_scope_ends() {
+        {
+            let _scope = MockScope::new();
+            unsafe {
+                mockable_string.mock_raw(|| MockResult::Return("mocked".to_string()));
+            }
+        }
+
+        mockable_string();
+    }
+
+    #[cfg(feature = "debug-checks")]
+    #[test]
+    #[should_panic(expected = "Mock set up with mock_raw called after its MockScope ended")]
+    fn when_context_mocked_raw_inside_scope_then_panics_after_scope_ends() {
+        let context = {
+            let _scope = MockScope::new();
+            unsafe {
+                MockContext::new()
+                    .mock_raw(mockable_string, || MockResult::Return("mocked".to_string()))
+            }
+        };
+
+        context.run(mockable_string);
+    }
+
+    #[cfg(feature = "debug-checks")]
+    #[test]
+    fn when_nested_scope_ends_then_mocks_bound_to_outer_scope_still_work() {
+        let _outer = MockScope::new();
+        unsafe {
+            mockable_string.mock_raw(|| MockResult::Return("mocked".to_string()));
+        }
+        drop(MockScope::new());
+
+        assert_eq!("mocked", mockable_string());
+    }
+}
+
+mod mock_with_info {
+    use super::*;
+    use std::thread;
+
+    #[mockable]
+    fn mockable_string() -> String {
+        "not mocked".to_string()
+    }
+
+    #[mockable]
+    fn mockable_join(a: &str, b: &str) -> String {
+        format!("{} {}", a, b)
+    }
+
+    fn describe(info: &CallInfo) -> String {
+        format!("{} {}", info.layer_depth(), info.is_reentrant())
+    }
+
+    #[test]
+    fn when_mocked_with_info_then_call_index_counts_calls_of_function() {
+        mockable_string.mock_with_info(|info| MockResult::Return(info.call_index().to_string()));
+
+        assert_eq!("0", mockable_string());
+        assert_eq!("1", mockable_string());
+        assert_eq!("2", mockable_string());
+    }
+
+    #[test]
+    fn when_mocked_with_info_again_then_call_index_keeps_counting() {
+        mockable_string.mock_with_info(|info| MockResult::Return(info.call_index().to_string()));
+        mockable_string();
+        mockable_string.mock_with_info(|info| MockResult::Return(info.call_index().to_string()));
+
+        assert_eq!("1", mockable_string());
+    }
+
+    #[test]
+    fn when_calls_handled_by_other_layers_then_call_index_counts_them() {
+        mockable_string.mock_with_info(|info| MockResult::Return(info.call_index().to_string()));
+
+        MockContext::new()
+            .mock_safe(mockable_string, || {
+                MockResult::Return("context".to_string())
+            })
+            .run(|| assert_eq!("context", mockable_string()));
+        assert_eq!("1", mockable_string());
+    }
+
+    #[test]
+    fn when_mocks_cleared_then_call_index_starts_from_zero() {
+        mockable_string.mock_with_info(|info| MockResult::Return(info.call_index().to_string()));
+        mockable_string();
+        mockable_string.clear_mock();
+        mockable_string.mock_with_info(|info| MockResult::Return(info.call_index().to_string()));
+
+        assert_eq!("0", mockable_string());
+    }
+
+    #[test]
+    fn when_mocked_with_info_then_receives_fn_name_and_thread() {
+        mockable_string.mock_with_info(|info| {
+            assert_eq!(thread::current().id(), info.thread().id());
+            MockResult::Return(info.fn_name().to_string())
+        });
+
+        assert!(mockable_string().ends_with("mock_with_info::mockable_string"));
+    }
+
+    #[test]
+    fn when_info_sent_to_other_thread_then_reports_calling_thread() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        mockable_string.mock_with_info(move |info| {
+            sender.send(info.clone()).unwrap();
+            MockResult::Return("mocked".to_string())
+        });
+        mockable_string();
+        let info = receiver.recv().unwrap();
+
+        let calling_thread = thread::current().id();
+        let reported_thread = thread::spawn(move || info.thread().id()).join().unwrap();
+
+        assert_eq!(calling_thread, reported_thread);
+    }
+
+    #[test]
+    fn when_mocked_with_info_then_receives_args() {
+        mockable_join.mock_with_info(|info, a, _| {
+            let b = if info.call_index() == 0 {
+                "first"
+            } else {
+                "next"
+            };
+            MockResult::Continue((a, b))
+        });
+
+        assert_eq!("a first", mockable_join("a", "b"));
+        assert_eq!("a next", mockable_join("a", "b"));
+    }
+
+    #[test]
+    fn when_mocked_with_info_then_receives_layer_depth() {
+        mockable_string.mock_with_info(|info| MockResult::Return(describe(info)));
+
+        assert_eq!("0 false", mockable_string());
+        MockContext::new()
+            .mock_with_info(mockable_string, |info| MockResult::Return(describe(info)))
+            .run(|| {
+                assert_eq!("1 false", mockable_string());
+                MockContext::new()
+                    .mock_with_info(mockable_string, |info| MockResult::Return(describe(info)))
+                    .run(|| assert_eq!("2 false", mockable_string()));
+                MockContext::new()
+                    .mock_safe(mockable_join, |a, b| MockResult::Continue((a, b)))
+                    .run(|| assert_eq!("1 false", mockable_string()));
+            });
+    }
+
+    #[test]
+    fn when_called_inside_mock_of_same_function_then_call_is_reentrant() {
+        mockable_string.mock_with_info(|info| MockResult::Return(describe(info)));
+
+        MockContext::new()
+            .mock_safe(mockable_string, || {
+                MockResult::Return(format!("outer {}", mockable_string()))
+            })
+            .run(|| assert_eq!("outer 0 true", mockable_string()));
+        assert_eq!("0 false", mockable_string());
+    }
+}
+
+mod active_mocks {
+    use super::*;
+
+    #[mockable]
+    fn mockable_string() -> String {
+        "not mocked".to_string()
+    }
+
+    #[mockable]
+    fn mockable_u32() -> u32 {
+        0
+    }
+
+    fn layers() -> Vec<(usize, Vec<&'static str>)> {
+        active_mocks()
+            .layers()
+            .iter()
+            .map(|layer| {
+                let fn_names = layer
+                    .fn_names()
+                    .iter()
+                    .map(|fn_name| fn_name.rsplit("::").next().unwrap())
+                    .collect();
+                (layer.depth(), fn_names)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn when_not_mocked_then_is_not_mocked_and_has_empty_thread_layer() {
+        assert!(!mockable_string.is_mocked());
+        assert_eq!(vec![(0, vec![])], layers());
+    }
+
+    #[test]
+    fn when_mocked_then_is_mocked_until_cleared() {
+        mockable_string.mock_safe(|| MockResult::Return("mocked".to_string()));
+
+        assert!(mockable_string.is_mocked());
+        assert!(!mockable_u32.is_mocked());
+        mockable_string.clear_mock();
+        assert!(!mockable_string.is_mocked());
+    }
+
+    #[test]
+    fn when_mocked_once_then_is_not_mocked_after_call() {
+        mockable_u32.mock_once(|| MockResult::Return(1));
+
+        assert!(mockable_u32.is_mocked());
+        mockable_u32();
+        assert!(!mockable_u32.is_mocked());
+    }
+
+    #[test]
+    fn when_mocked_in_nested_contexts_then_lists_mocks_of_every_layer() {
+        mockable_string.mock_safe(|| MockResult::Return("mocked".to_string()));
+
+        MockContext::new()
+            .mock_safe(mockable_u32, || MockResult::Return(1))
+            .mock_safe(mockable_string, || {
+                MockResult::Return("context".to_string())
+            })
+            .run(|| {
+                assert!(mockable_u32.is_mocked());
+                MockContext::new().run(|| {
+                    assert_eq!(
+                        vec![
+                            (0, vec!["mockable_string"]),
+                            (1, vec!["mockable_string", "mockable_u32"]),
+                            (2, vec![]),
+                        ],
+                        layers()
+                    );
+                });
+            });
+        assert!(!mockable_u32.is_mocked());
+        assert_eq!(vec![(0, vec!["mockable_string"])], layers());
+    }
+
+    #[test]
+    fn when_displayed_then_lists_function_names_under_layers() {
+        mockable_string.mock_safe(|| MockResult::Return("mocked".to_string()));
+
+        MockContext::new()
+            .mock_safe(mockable_u32, || MockResult::Return(1))
+            .run(|| {
+                let expected = format!(
+                    "thread mocks:\n    {}\nMockContext 1 mocks:\n    {}\n",
+                    std::any::type_name_of_val(&mockable_string),
+                    std::any::type_name_of_val(&mockable_u32),
+                );
+                assert_eq!(expected, active_mocks().to_string());
+            });
+    }
+}
+
+mod unused_mocks {
+    use super::*;
+
+    #[mockable]
+    fn mockable_string() -> String {
+        "not mocked".to_string()
+    }
+
+    #[mockable]
+    fn mockable_u32() -> u32 {
+        0
+    }
+
+    struct Counter(u32);
+
+    #[mockable(substitute)]
+    impl Counter {
+        fn increment(&mut self) {
+            self.0 += 1;
+        }
+
+        fn get(&self) -> u32 {
+            self.0
+        }
+    }
+
+    #[derive(Default)]
+    struct FakeCounter;
+
+    impl CounterApi for FakeCounter {
+        fn increment(&mut self) {}
+
+        fn get(&mut self) -> u32 {
+            10
+        }
+    }
+
+    #[test]
+    fn when_mock_unused_then_run_returns() {
+        let result = MockContext::new()
+            .mock_safe(mockable_u32, || MockResult::Return(1))
+            .run(|| "returned");
+
+        assert_eq!("returned", result);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "MockContext mocks were never called: mocking::unused_mocks::mockable_u32"
+    )]
+    fn when_mock_unused_and_denied_then_panics_naming_function() {
+        MockContext::new()
+            .mock_safe(mockable_string, || MockResult::Return("mocked".to_string()))
+            .mock_safe(mockable_u32, || MockResult::Return(1))
+            .deny_unused()
+            .run(|| assert_eq!("mocked", mockable_string()));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "never called: mocking::unused_mocks::mockable_string, mocking::unused_mocks::mockable_u32"
+    )]
+    fn when_many_mocks_unused_and_denied_then_panics_naming_all_functions() {
+        MockContext::new()
+            .mock_safe(mockable_u32, || MockResult::Return(1))
+            .mock_safe(mockable_string, || MockResult::Return("mocked".to_string()))
+            .deny_unused()
+            .run(|| ());
+    }
+
+    #[test]
+    fn when_all_mocks_called_and_denied_then_run_returns() {
+        MockContext::new()
+            .mock_safe(mockable_string, || MockResult::Continue(()))
+            .mock_once(mockable_u32, || MockResult::Return(1))
+            .deny_unused()
+            .run(|| {
+                assert_eq!("not mocked", mockable_string());
+                assert_eq!(1, mockable_u32());
+            });
+    }
+
+    #[test]
+    fn when_mock_shadowed_by_inner_context_and_denied_then_panics() {
+        let result = std::panic::catch_unwind(|| {
+            MockContext::new()
+                .mock_safe(mockable_u32, || MockResult::Return(1))
+                .deny_unused()
+                .run(|| {
+                    MockContext::new()
+                        .mock_safe(mockable_u32, || MockResult::Return(2))
+                        .deny_unused()
+                        .run(|| assert_eq!(2, mockable_u32()));
+                });
+        });
+
+        assert!(result.is_err());
+        assert_eq!(0, mockable_u32());
+    }
+
+    #[test]
+    #[should_panic(expected = "failure in run")]
+    fn when_run_panics_and_mock_unused_and_denied_then_reports_only_run_panic() {
+        MockContext::new()
+            .mock_safe(mockable_u32, || MockResult::Return(1))
+            .deny_unused()
+            .run(|| panic!("failure in run"));
+    }
+
+    #[test]
+    fn when_substituted_methods_unused_and_denied_then_run_returns() {
+        let counter = Counter(0);
+        MockContext::new()
+            .substitute::<Counter, _>(FakeCounter)
+            .mock_safe(mockable_u32, || MockResult::Return(1))
+            .deny_unused()
+            .run(|| {
+                assert_eq!(10, counter.get());
+                assert_eq!(1, mockable_u32());
+            });
+    }
+}
+
+mod mock_panics {
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+
+    #[mockable]
+    fn debug_args(a: u32, b: &str) -> String {
+        format!("{} {}", a, b)
+    }
+
+    struct NotDebug;
+
+    #[mockable]
+    fn not_debug_args(_: NotDebug) -> u32 {
+        0
+    }
+
+    #[mockable]
+    fn owned_args(a: u32, b: String) -> String {
+        format!("{} {}", a, b)
+    }
+
+    #[mockable]
+    fn generic_args<T: std::fmt::Debug>(t: T) -> String {
+        format!("{:?}", t)
+    }
+
+    #[mockable]
+    fn mixed_args(a: u32, _: NotDebug) -> u32 {
+        a
+    }
+
+    fn panic_message<T>(f: impl FnOnce() -> T) -> String {
+        let payload = panic::catch_unwind(AssertUnwindSafe(f))
+            .err()
+            .expect("Function didn't panic");
+        payload
+            .downcast::<String>()
+            .map(|message| *message)
+            .expect("Panic payload is not a String")
+    }
+
+    #[test]
+    fn when_mock_panics_then_message_has_function_and_args() {
+        debug_args.mock_safe(|_, _| panic!("failure in mock"));
+
+        let message = panic_message(|| debug_args(1, "x"));
+
+        assert_eq!(
+            "mock of mocking::mock_panics::debug_args called with (1, \"x\") panicked: failure in mock",
+            message
+        );
+    }
+
+    #[test]
+    fn when_mock_with_non_debug_args_panics_then_message_has_function() {
+        not_debug_args.mock_safe(|_| panic!("failure in mock"));
+
+        let message = panic_message(|| not_debug_args(NotDebug));
+
+        assert_eq!(
+            "mock of mocking::mock_panics::not_debug_args panicked: failure in mock",
+            message
+        );
+    }
+
+    #[test]
+    fn when_mock_with_owned_args_panics_then_message_has_them() {
+        owned_args.mock_safe(|_, _| panic!("failure in mock"));
+
+        let message = panic_message(|| owned_args(1, "x".to_string()));
+
+        assert_eq!(
+            "mock of mocking::mock_panics::owned_args called with (1, \"x\") panicked: failure in mock",
+            message
+        );
+    }
+
+    #[test]
+    fn when_mock_with_debug_generic_args_panics_then_message_has_them() {
+        generic_args::<u32>.mock_safe(|_| panic!("failure in mock"));
+
+        let message = panic_message(|| generic_args(1_u32));
+
+        assert_eq!(
+            "mock of mocking::mock_panics::generic_args<u32> called with (1) panicked: failure in mock",
+            message
+        );
+    }
+
+    #[test]
+    fn when_mock_with_some_non_debug_args_panics_then_message_skips_them() {
+        mixed_args.mock_safe(|_, _| panic!("failure in mock"));
+
+        let message = panic_message(|| mixed_args(1, NotDebug));
+
+        assert_eq!(
+            "mock of mocking::mock_panics::mixed_args called with (1, _) panicked: failure in mock",
+            message
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "failure in mock")]
+    fn when_mock_panics_then_original_message_matches_should_panic() {
+        debug_args.mock_safe(|a, _| panic!("failure in mock {}", a));
+
+        debug_args(1, "x");
+    }
+
+    #[test]
+    fn when_mock_panics_with_non_text_payload_then_payload_is_preserved() {
+        debug_args.mock_safe(|_, _| panic::panic_any(42_u32));
+
+        let payload = panic::catch_unwind(|| debug_args(1, "x")).unwrap_err();
+
+        assert_eq!(Some(&42), payload.downcast_ref::<u32>());
+    }
+
+    #[test]
+    fn when_mock_in_context_panics_then_mock_is_removed_with_context() {
+        let message = panic_message(|| {
+            MockContext::new()
+                .mock_safe(debug_args, |_, _| panic!("failure in mock"))
+                .run(|| debug_args(1, "x"))
+        });
+
+        assert!(message.ends_with("debug_args called with (1, \"x\") panicked: failure in mock"));
+        assert_eq!("1 x", debug_args(1, "x"));
+    }
+
+    #[test]
+    fn when_mock_calls_panicking_mock_then_message_has_both_functions() {
+        debug_args.mock_safe(|_, _| panic!("failure in mock"));
+        not_debug_args.mock_safe(|_| MockResult::Return(debug_args(2, "y").len() as u32));
+
+        let message = panic_message(|| not_debug_args(NotDebug));
+
+        assert_eq!(
+            "mock of mocking::mock_panics::not_debug_args panicked: \
+             mock of mocking::mock_panics::debug_args called with (2, \"y\") panicked: failure in mock",
+            message
+        );
+    }
+}
+
+mod try_run {
+    use super::*;
+
+    #[mockable]
+    fn mockable_string() -> String {
+        "not mocked".to_string()
+    }
+
+    #[mockable]
+    fn mockable_u32() -> u32 {
+        0
+    }
+
+    #[test]
+    fn when_all_mocks_called_then_returns_result() {
+        let result = MockContext::new()
+            .mock_safe(mockable_u32, || MockResult::Return(1))
+            .deny_unused()
+            .try_run(mockable_u32);
+
+        assert_eq!(Ok(1), result);
+    }
+
+    #[test]
+    fn when_mocks_unused_and_not_denied_then_returns_result() {
+        let result = MockContext::new()
+            .mock_safe(mockable_u32, || MockResult::Return(1))
+            .try_run(|| "returned");
+
+        assert_eq!(Ok("returned"), result);
+    }
+
+    #[test]
+    fn when_mocks_unused_and_denied_then_returns_error_and_removes_mocks() {
+        let result = MockContext::new()
+            .mock_safe(mockable_u32, || MockResult::Return(1))
+            .mock_safe(mockable_string, || MockResult::Return("mocked".to_string()))
+            .deny_unused()
+            .try_run(|| ());
+
+        let expected = MockError::UnusedMocks {
+            fn_names: vec![
+                "mocking::try_run::mockable_string",
+                "mocking::try_run::mockable_u32",
+            ],
+        };
+        assert_eq!(Err(expected), result);
+        assert_eq!(0, mockable_u32());
+        assert_eq!(vec![(0, vec![])], active_mocks_depths());
+    }
+
+    #[test]
+    fn when_error_displayed_then_names_all_functions() {
+        let error = MockError::UnusedMocks {
+            fn_names: vec!["a::f", "b::g"],
+        };
+
+        assert_eq!(
+            "MockContext mocks were never called: a::f, b::g",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn when_error_returned_from_test_helper_then_converts_to_boxed_error() {
+        fn helper() -> Result<(), Box<dyn std::error::Error>> {
+            MockContext::new()
+                .mock_safe(mockable_u32, || MockResult::Return(1))
+                .deny_unused()
+                .try_run(|| ())?;
+            Ok(())
+        }
+
+        let error = helper().unwrap_err();
+
+        assert_eq!(
+            "MockContext mocks were never called: mocking::try_run::mockable_u32",
+            error.to_string()
+        );
+    }
+
+    fn active_mocks_depths() -> Vec<(usize, Vec<&'static str>)> {
+        active_mocks()
+            .layers()
+            .iter()
+            .map(|layer| (layer.depth(), layer.fn_names().to_vec()))
+            .collect()
+    }
+}
+
+mod debug_diff {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Request {
+        id: u32,
+        tags: Vec<&'static str>,
+        body: &'static str,
+    }
+
+    #[mockable]
+    fn send(_request: Request) {}
+
+    fn request(id: u32, body: &'static str) -> Request {
+        Request {
+            id,
+            tags: vec!["a", "b", "c", "d", "e", "f", "g", "h"],
+            body,
+        }
+    }
+
+    #[test]
+    fn when_values_differ_then_shows_differing_lines_with_context() {
+        let diff = DebugDiff::new(&request(1, "body"), &request(2, "body")).colored(false);
+
+        let expected = "\
+Difference (-expected +actual):
+  Request {
+-     id: 1,
++     id: 2,
+      tags: [
+          \"a\",
+          \"b\",
+  ...
+";
+        assert!(!diff.is_same());
+        assert_eq!(expected, diff.to_string());
+    }
+
+    #[test]
+    fn when_values_differ_in_many_places_then_elides_unchanged_lines_between() {
+        let diff = DebugDiff::new(&request(1, "body"), &request(2, "other")).colored(false);
+
+        let expected = "\
+Difference (-expected +actual):
+  Request {
+-     id: 1,
++     id: 2,
+      tags: [
+          \"a\",
+          \"b\",
+  ...
+          \"g\",
+          \"h\",
+      ],
+-     body: \"body\",
++     body: \"other\",
+  }
+";
+        assert_eq!(expected, diff.to_string());
+    }
+
+    #[test]
+    fn when_item_added_then_shows_only_added_line() {
+        let diff = DebugDiff::new(&(1, vec![1, 2]), &(1, vec![1, 3, 2])).colored(false);
+
+        let expected = "\
+Difference (-expected +actual):
+  ...
+      1,
+      [
+          1,
++         3,
+          2,
+      ],
+  )
+";
+        assert_eq!(expected, diff.to_string());
+    }
+
+    #[test]
+    fn when_colored_then_differing_lines_are_colored() {
+        let diff = DebugDiff::new(&1, &2).colored(true);
+
+        let expected = "\
+Difference (-expected +actual):
+\x1b[31m- 1\x1b[0m
+\x1b[32m+ 2\x1b[0m
+";
+        assert_eq!(expected, diff.to_string());
+    }
+
+    #[test]
+    fn when_values_same_then_is_same() {
+        let diff = DebugDiff::new(&request(1, "body"), &request(1, "body"));
+
+        assert!(diff.is_same());
+        assert_eq!(
+            "Debug output of expected and actual values is the same",
+            diff.to_string()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "+     id: 2,")]
+    fn when_used_in_mock_assertion_then_panic_message_has_diff() {
+        send.mock_safe(|request| {
+            let expected = self::request(1, "body");
+            assert!(
+                request == expected,
+                "{}",
+                DebugDiff::new(&expected, &request).colored(false)
+            );
+            MockResult::Return(())
+        });
+
+        send(request(2, "body"));
+    }
+
+    #[test]
+    fn when_args_differ_then_assert_args_eq_panics_with_diff() {
+        send.mock_safe(|request| {
+            assert_args_eq(&self::request(1, "body"), &request);
+            MockResult::Return(())
+        });
+
+        let payload = std::panic::catch_unwind(|| send(request(2, "body"))).unwrap_err();
+
+        // Diff is colored if stderr of the test is a terminal
+        let message = ["\x1b[31m", "\x1b[32m", "\x1b[0m"].iter().fold(
+            payload.downcast_ref::<String>().unwrap().clone(),
+            |message, color| message.replace(color, ""),
+        );
+        let expected = "\
+Mock arguments differ from expected
+Difference (-expected +actual):
+  Request {
+-     id: 1,
++     id: 2,
+      tags: [
+";
+        assert!(message.contains(expected), "{}", message);
+    }
+
+    #[test]
+    fn when_args_equal_then_assert_args_eq_passes() {
+        send.mock_safe(|request| {
+            assert_args_eq(&self::request(1, "body"), &request);
+            MockResult::Return(())
+        });
+
+        send(request(1, "body"));
+    }
+}
+
+mod named_layers {
+    use super::*;
+
+    #[mockable]
+    fn mockable_string() -> String {
+        "not mocked".to_string()
+    }
+
+    #[mockable]
+    fn mockable_u32() -> u32 {
+        0
+    }
+
+    fn return_string(value: &'static str) -> impl FnMut() -> MockResult<(), String> {
+        move || MockResult::Return(value.to_string())
+    }
+
+    fn layer_names() -> Vec<(usize, Option<&'static str>)> {
+        active_mocks()
+            .layers()
+            .iter()
+            .map(|layer| (layer.depth(), layer.name()))
+            .collect()
+    }
+
+    #[test]
+    fn when_mocked_in_pushed_layer_then_mocks_until_guard_dropped() {
+        let guard = layer("fixture").push();
+        layer("fixture").mock_safe(mockable_string, return_string("fixture"));
+
+        assert_eq!("fixture", mockable_string());
+        drop(guard);
+
+        assert_eq!("not mocked", mockable_string());
+        assert!(!mockable_string.is_mocked());
+        assert_eq!(vec![(0, None)], layer_names());
+    }
+
+    #[test]
+    fn when_layers_stacked_then_upper_layer_overrides_lower() {
+        mockable_string.mock_safe(return_string("thread"));
+        let _fixture = layer("fixture").push();
+        layer("fixture").mock_safe(mockable_string, return_string("fixture"));
+        let step = layer("step").push();
+        layer("step").mock_safe(mockable_string, return_string("step"));
+
+        assert_eq!("step", mockable_string());
+        drop(step);
+
+        assert_eq!("fixture", mockable_string());
+    }
+
+    #[test]
+    fn when_mocked_in_lower_layer_after_pushing_upper_then_upper_still_overrides() {
+        let _fixture = layer("fixture").push();
+        let _step = layer("step").push();
+        layer("step").mock_safe(mockable_string, return_string("step"));
+        layer("fixture").mock_safe(mockable_string, return_string("fixture"));
+
+        assert_eq!("step", mockable_string());
+    }
+
+    #[test]
+    fn when_mocked_again_in_layer_then_replaces_previous_mock() {
+        let _fixture = layer("fixture").push();
+        layer("fixture").mock_safe(mockable_string, return_string("first"));
+        layer("fixture").mock_safe(mockable_string, || MockResult::Continue(()));
+
+        assert_eq!("not mocked", mockable_string());
+    }
+
+    #[test]
+    fn when_lower_layer_dropped_first_then_upper_layer_stays() {
+        let fixture = layer("fixture").push();
+        layer("fixture").mock_safe(mockable_string, return_string("fixture"));
+        layer("fixture").mock_safe(mockable_u32, || MockResult::Return(1));
+        let step = layer("step").push();
+        layer("step").mock_safe(mockable_string, || MockResult::Continue(()));
+
+        drop(fixture);
+
+        assert_eq!("not mocked", mockable_string());
+        assert_eq!(0, mockable_u32());
+        assert_eq!(vec![(0, None), (1, Some("step"))], layer_names());
+        layer("step").mock_safe(mockable_u32, || MockResult::Return(2));
+        assert_eq!(2, mockable_u32());
+        drop(step);
+        assert!(!mockable_string.is_mocked());
+        assert!(!mockable_u32.is_mocked());
+    }
+
+    #[test]
+    fn when_layer_cleared_then_mocks_removed_and_layer_kept() {
+        let _fixture = layer("fixture").push();
+        layer("fixture").mock_safe(mockable_string, return_string("fixture"));
+        layer("fixture").mock_safe(mockable_u32, || MockResult::Return(1));
+
+        clear_layer("fixture");
+
+        assert_eq!("not mocked", mockable_string());
+        assert_eq!(0, mockable_u32());
+        layer("fixture").mock_safe(mockable_u32, || MockResult::Return(2));
+        assert_eq!(2, mockable_u32());
+    }
+
+    #[test]
+    fn when_name_pushed_twice_then_innermost_layer_is_used() {
+        let _outer = layer("fixture").push();
+        layer("fixture").mock_safe(mockable_string, return_string("outer"));
+        let inner = layer("fixture").push();
+        layer("fixture").mock_safe(mockable_string, return_string("inner"));
+
+        assert_eq!("inner", mockable_string());
+        layer("fixture").clear();
+        assert_eq!("outer", mockable_string());
+        drop(inner);
+        assert_eq!("outer", mockable_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "No mock layer named `missing` is pushed")]
+    fn when_mocked_in_layer_not_pushed_then_panics() {
+        layer("missing").mock_safe(mockable_u32, || MockResult::Return(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "No mock layer named `missing` is pushed")]
+    fn when_layer_not_pushed_cleared_then_panics() {
+        clear_layer("missing");
+    }
+
+    #[test]
+    fn when_layers_pushed_then_active_mocks_shows_names() {
+        let _fixture = layer("fixture").push();
+        layer("fixture").mock_safe(mockable_u32, || MockResult::Return(1));
+
+        MockContext::new()
+            .mock_safe(mockable_string, return_string("context"))
+            .run(|| {
+                let _step = layer("step").push();
+
+                assert_eq!(
+                    vec![
+                        (0, None),
+                        (1, Some("fixture")),
+                        (2, None),
+                        (3, Some("step"))
+                    ],
+                    layer_names()
+                );
+                assert_eq!(
+                    "thread mocks:\n\
+                     layer 1 `fixture` mocks:\n    mocking::named_layers::mockable_u32\n\
+                     MockContext 2 mocks:\n    mocking::named_layers::mockable_string\n\
+                     layer 3 `step` mocks:\n",
+                    active_mocks().to_string()
+                );
+            });
+    }
+
+    #[test]
+    fn when_layer_pushed_in_context_outlives_it_then_context_removes_only_its_layer() {
+        let step = MockContext::new()
+            .mock_safe(mockable_u32, || MockResult::Return(1))
+            .run(|| {
+                let step = layer("step").push();
+                layer("step").mock_safe(mockable_string, return_string("step"));
+                step
+            });
+
+        assert_eq!(0, mockable_u32());
+        assert_eq!("step", mockable_string());
+        assert_eq!(vec![(0, None), (1, Some("step"))], layer_names());
+        drop(step);
+        assert_eq!("not mocked", mockable_string());
+    }
+}
+
+mod mock_set {
+    use super::*;
+
+    #[mockable]
+    fn mockable_string() -> String {
+        "not mocked".to_string()
+    }
+
+    #[mockable]
+    fn mockable_u32(a: u32) -> u32 {
+        a
+    }
+
+    fn counting_set() -> MockSet {
+        MockSet::new().mock(mockable_u32, || {
+            let mut calls = 0;
+            move |_| {
+                calls += 1;
+                MockResult::Return(calls)
+            }
+        })
+    }
+
+    #[test]
+    fn when_context_with_set_then_mocks_from_factories_are_set_up() {
+        let set = MockSet::new()
+            .mock(mockable_string, || || MockResult::Return("set".to_string()))
+            .mock(mockable_u32, || |a| MockResult::Return(a * 2));
+
+        MockContext::new().with(&set).run(|| {
+            assert_eq!("set", mockable_string());
+            assert_eq!(4, mockable_u32(2));
+        });
+
+        assert_eq!("not mocked", mockable_string());
+    }
+
+    #[test]
+    fn when_set_used_many_times_then_every_context_gets_fresh_mocks() {
+        let set = counting_set();
+
+        MockContext::new().with(&set).run(|| {
+            assert_eq!(1, mockable_u32(0));
+            assert_eq!(2, mockable_u32(0));
+        });
+        MockContext::new().with(&set).run(|| {
+            assert_eq!(1, mockable_u32(0));
+        });
+    }
+
+    #[test]
+    fn when_mocked_in_context_after_set_then_replaces_mock_of_set() {
+        MockContext::new()
+            .with(&counting_set())
+            .mock_safe(mockable_u32, |a| MockResult::Return(a + 10))
+            .run(|| assert_eq!(11, mockable_u32(1)));
+    }
+
+    #[test]
+    fn when_mocked_again_in_set_then_replaces_previous_entry() {
+        let set = counting_set().mock(mockable_u32, || |a| MockResult::Return(a + 10));
+
+        MockContext::new()
+            .with(&set)
+            .run(|| assert_eq!(11, mockable_u32(1)));
+    }
+
+    #[test]
+    fn when_set_extended_then_other_set_replaces_same_functions() {
+        let other = MockSet::new()
+            .mock(mockable_u32, || |a| MockResult::Return(a + 10))
+            .mock(mockable_string, || {
+                || MockResult::Return("other".to_string())
+            });
+
+        let set = counting_set().extend(other);
+
+        MockContext::new().with(&set).run(|| {
+            assert_eq!(11, mockable_u32(1));
+            assert_eq!("other", mockable_string());
+        });
+    }
+
+    #[test]
+    fn when_mocks_of_set_unused_and_denied_then_run_returns() {
+        MockContext::new()
+            .with(&counting_set())
+            .deny_unused()
+            .run(|| ());
+    }
+
+    #[test]
+    #[should_panic(expected = "never called: mocking::mock_set::mockable_u32")]
+    fn when_replacing_mock_unused_and_denied_then_panics() {
+        MockContext::new()
+            .with(&counting_set())
+            .mock_safe(mockable_u32, |a| MockResult::Return(a + 10))
+            .deny_unused()
+            .run(|| ());
+    }
+
+    #[test]
+    fn when_set_created_then_is_empty_until_mocked() {
+        assert!(MockSet::new().is_empty());
+        assert!(!counting_set().is_empty());
+    }
 }
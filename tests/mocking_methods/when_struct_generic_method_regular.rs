@@ -106,11 +106,12 @@ mod and_method_is_ref_mut_method {
     #[test]
     fn and_continue_mocked_then_runs_with_modified_args() {
         let mut struct_2 = Struct(2u8);
-        let struct_3 = Struct(3u8);
+        let mut struct_3 = Struct(3u8);
+        let struct_3_ptr = &mut struct_3 as *mut _;
         let mut struct_str = Struct("str");
         unsafe {
             Struct::<u8>::ref_mut_method
-                .mock_raw(|_, b| MockResult::Continue((as_mut(&struct_3), !b)));
+                .mock_raw(|_, b| MockResult::Continue((&mut *struct_3_ptr, !b)));
         }
 
         assert_eq!("0 false", struct_2.ref_mut_method(true));
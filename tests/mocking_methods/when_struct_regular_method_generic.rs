@@ -108,11 +108,12 @@ mod and_method_is_ref_mut_method {
     #[test]
     fn and_continue_mocked_then_runs_with_modified_args() {
         let mut struct_2 = Struct(2);
-        let struct_3 = Struct(3);
+        let mut struct_3 = Struct(3);
+        let struct_3_ptr = &mut struct_3 as *mut _;
         let mut struct_4 = Struct(4);
         unsafe {
             Struct::ref_mut_method::<f32>
-                .mock_raw(|_, b, c| MockResult::Continue((as_mut(&struct_3), !b, c + 1.)));
+                .mock_raw(|_, b, c| MockResult::Continue((&mut *struct_3_ptr, !b, c + 1.)));
         }
 
         assert_eq!("6 false 2.5", struct_2.ref_mut_method(true, 1.5f32));
@@ -0,0 +1,200 @@
+// Mocking without `nightly` feature, where mock closures arguments types must be annotated
+#![cfg(not(feature = "nightly"))]
+
+use mocktopus::macros::*;
+use mocktopus::mocking::*;
+
+#[mockable]
+fn no_args() -> u32 {
+    0
+}
+
+#[mockable]
+fn two_args(a: u32, b: &str) -> String {
+    format!("{} {}", a, b)
+}
+
+#[mockable]
+#[allow(clippy::too_many_arguments)]
+fn sixteen_args(
+    a0: u8,
+    a1: u8,
+    a2: u8,
+    a3: u8,
+    a4: u8,
+    a5: u8,
+    a6: u8,
+    a7: u8,
+    a8: u8,
+    a9: u8,
+    a10: u8,
+    a11: u8,
+    a12: u8,
+    a13: u8,
+    a14: u8,
+    a15: u8,
+) -> u32 {
+    [
+        a0, a1, a2, a3, a4, a5, a6, a7, a8, a9, a10, a11, a12, a13, a14, a15,
+    ]
+    .iter()
+    .map(|a| *a as u32)
+    .sum()
+}
+
+#[test]
+fn when_no_args_mocked_then_returns_mock_result() {
+    no_args.mock_safe(|| MockResult::Return(1));
+
+    assert_eq!(1, no_args());
+}
+
+#[test]
+fn when_args_mocked_with_annotated_closure_then_returns_mock_result() {
+    two_args.mock_safe(|a: u32, b: &str| MockResult::Return(format!("{} {}", b, a)));
+
+    assert_eq!("x 1", two_args(1, "x"));
+}
+
+#[test]
+fn when_args_mocked_to_continue_then_runs_with_modified_args() {
+    two_args.mock_named(|mut args: two_args_args| {
+        args.a += 1;
+        MockResult::Continue(args)
+    });
+
+    assert_eq!("2 x", two_args(1, "x"));
+}
+
+#[test]
+fn when_sixteen_args_mocked_then_returns_mock_result() {
+    sixteen_args.mock_safe(
+        |a0: u8,
+         _: u8,
+         _: u8,
+         _: u8,
+         _: u8,
+         _: u8,
+         _: u8,
+         _: u8,
+         _: u8,
+         _: u8,
+         _: u8,
+         _: u8,
+         _: u8,
+         _: u8,
+         _: u8,
+         a15: u8| { MockResult::Return(a0 as u32 * a15 as u32) },
+    );
+
+    assert_eq!(
+        6,
+        sixteen_args(2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 3)
+    );
+}
+
+#[test]
+fn when_mocked_once_then_mocks_single_call() {
+    let mocked = "mocked".to_string();
+    two_args.mock_once(move |_: u32, _: &str| MockResult::Return(mocked));
+
+    assert_eq!("mocked", two_args(1, "x"));
+    assert_eq!("1 x", two_args(1, "x"));
+}
+
+#[test]
+fn when_mocked_named_then_closure_arguments_are_inferred() {
+    two_args.mock_named(|args: two_args_args| MockResult::Return(args.b.to_string()));
+
+    assert_eq!("x", two_args(1, "x"));
+}
+
+#[test]
+fn when_mocked_in_context_then_mocks_only_inside_run_closure() {
+    let mut calls = 0;
+    MockContext::new()
+        .mock_safe(two_args, |a: u32, _: &str| {
+            calls += 1;
+            MockResult::Return(a.to_string())
+        })
+        .run(|| {
+            assert_eq!("1", two_args(1, "x"));
+        });
+
+    assert_eq!("1 x", two_args(1, "x"));
+    assert_eq!(1, calls);
+}
+
+#[test]
+fn when_mocked_with_info_then_annotated_closure_receives_call_info() {
+    two_args.mock_with_info(|info: &CallInfo, a: u32, b: &str| {
+        MockResult::Return(format!("{} {} {}", info.call_index(), a, b))
+    });
+
+    assert_eq!("0 1 a", two_args(1, "a"));
+    assert_eq!("1 1 a", two_args(1, "a"));
+    MockContext::new()
+        .mock_with_info(no_args, |info: &CallInfo| {
+            MockResult::Return(info.layer_depth() as u32)
+        })
+        .run(|| assert_eq!(1, no_args()));
+}
+
+#[test]
+fn when_mock_panics_then_message_has_function_and_args() {
+    two_args.mock_safe(|_: u32, _: &str| panic!("failure in mock"));
+
+    let payload = std::panic::catch_unwind(|| two_args(1, "x")).unwrap_err();
+
+    assert_eq!(
+        Some("mock of mocking_stable::two_args called with (1, \"x\") panicked: failure in mock"),
+        payload.downcast_ref::<String>().map(String::as_str)
+    );
+}
+
+mod generated_items {
+    use super::*;
+
+    pub struct Counter(u32);
+
+    #[mockable(substitute, mocks)]
+    impl Counter {
+        pub fn add(&mut self, value: u32) -> u32 {
+            self.0 += value;
+            self.0
+        }
+    }
+
+    #[derive(Default)]
+    struct FakeCounter(u32);
+
+    impl CounterApi for FakeCounter {
+        fn add(&mut self, value: u32) -> u32 {
+            self.0 += value * 10;
+            self.0
+        }
+    }
+
+    #[test]
+    fn when_substituted_then_fake_serves_calls() {
+        let mut counter = Counter(0);
+        MockContext::new()
+            .substitute::<Counter, _>(FakeCounter::default())
+            .run(|| {
+                assert_eq!(10, counter.add(1));
+                assert_eq!(30, counter.add(2));
+            });
+
+        assert_eq!(1, counter.add(1));
+    }
+
+    #[test]
+    fn when_mocked_with_builder_then_closure_arguments_are_inferred() {
+        let mut counter = Counter(0);
+        CounterMocks::new()
+            .add(|counter, value| MockResult::Continue((counter, value * 2)))
+            .run(|| {
+                assert_eq!(4, counter.add(2));
+            });
+    }
+}
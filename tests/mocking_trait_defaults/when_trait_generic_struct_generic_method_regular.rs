@@ -131,12 +131,13 @@ mod and_method_is_ref_mut_method {
     #[test]
     fn and_continue_mocked_then_runs_with_modified_args() {
         let mut struct_2 = Struct(2u8);
-        let struct_3 = Struct(3u8);
+        let mut struct_3 = Struct(3u8);
+        let struct_3_ptr = &mut struct_3 as *mut _;
         let mut struct_str = Struct("str");
         let mut struct_4 = Struct(4u8);
         unsafe {
             <Struct<u8> as Trait<char>>::ref_mut_method.mock_raw(|_, b, c| {
-                MockResult::Continue((as_mut(&struct_3), !b, c.to_ascii_uppercase()))
+                MockResult::Continue((&mut *struct_3_ptr, !b, c.to_ascii_uppercase()))
             });
         }
 
@@ -0,0 +1,77 @@
+// Link-time registry of mockable functions
+#![cfg(feature = "registry")]
+
+use mocktopus::macros::*;
+use mocktopus::mocking::*;
+
+#[mockable]
+fn registered_fn(a: u32) -> u32 {
+    a
+}
+
+#[allow(dead_code)]
+fn not_registered_fn() {}
+
+struct Struct;
+
+#[mockable]
+impl Struct {
+    fn registered_method(&self) {}
+}
+
+trait Trait {
+    fn registered_trait_method(&self);
+}
+
+#[mockable]
+impl Trait for Struct {
+    fn registered_trait_method(&self) {}
+}
+
+fn find(name: &str) -> Option<&'static RegisteredFn> {
+    registry().find(|registered| registered.name() == name)
+}
+
+#[test]
+fn when_fn_annotated_then_registers_its_path_signature_and_location() {
+    let registered = find("registered_fn").expect("registered_fn not in registry");
+
+    assert_eq!("registry::registered_fn", registered.path());
+    assert_eq!("registry", registered.module_path());
+    let signature: String = registered.signature().split_whitespace().collect();
+    assert_eq!("fnregistered_fn(a:u32)->u32", signature);
+    assert_eq!(file!(), registered.file());
+    assert_eq!(8, registered.line());
+    assert_eq!(4, registered.column());
+    assert_eq!(
+        format!("registry::registered_fn at {}:8:4", file!()),
+        registered.to_string()
+    );
+}
+
+#[test]
+fn when_fn_not_annotated_then_does_not_register_it() {
+    assert!(find("not_registered_fn").is_none());
+}
+
+#[test]
+fn when_impl_annotated_then_registers_its_methods_with_types() {
+    let method = find("registered_method").expect("registered_method not in registry");
+    let trait_method =
+        find("registered_trait_method").expect("registered_trait_method not in registry");
+    Struct.registered_method();
+    Struct.registered_trait_method();
+
+    assert_eq!("registry::Struct::registered_method", method.path());
+    assert_eq!(
+        "registry::<Struct as Trait>::registered_trait_method",
+        trait_method.path()
+    );
+}
+
+#[test]
+fn when_registered_fn_mocked_then_mocks_it() {
+    registered_fn.mock_safe(|_| MockResult::Return(2));
+
+    assert_eq!(2, registered_fn(1));
+}
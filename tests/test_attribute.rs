@@ -0,0 +1,163 @@
+// Tests annotated with `#[mocktopus::test]` and `#[mocktopus::tokio_test]`
+use mocktopus::macros::*;
+use mocktopus::mocking::*;
+use std::panic;
+
+#[mockable]
+fn no_args() -> u32 {
+    0
+}
+
+#[mockable]
+fn other_no_args() -> u32 {
+    0
+}
+
+#[mock_fixture]
+fn defaults() -> MockSet {
+    MockSet::new()
+        .mock(no_args, || || MockResult::Return(10))
+        .mock(other_no_args, || {
+            let mut calls = 0;
+            move || {
+                calls += 1;
+                MockResult::Return(calls)
+            }
+        })
+}
+
+mod fixtures {
+    use super::*;
+
+    #[mock_fixture]
+    pub fn overrides() -> MockSet {
+        MockSet::new().mock(no_args, || || MockResult::Return(20))
+    }
+}
+
+#[mocktopus::test]
+fn when_mocked_in_test_then_returns_mock_result() {
+    no_args.mock_safe(|| MockResult::Return(1));
+
+    assert_eq!(1, no_args());
+}
+
+#[mocktopus::test]
+fn when_test_run_then_runs_inside_fresh_context() {
+    let depths: Vec<_> = active_mocks()
+        .layers()
+        .iter()
+        .map(MockLayerInfo::depth)
+        .collect();
+
+    assert_eq!(vec![0, 1], depths);
+}
+
+#[mocktopus::test]
+fn when_test_returns_result_then_question_mark_is_usable() -> Result<(), String> {
+    no_args.mock_safe(|| MockResult::Return(1));
+
+    let value: u32 = "1".parse().map_err(|_| "not a number".to_string())?;
+    assert_eq!(value, no_args());
+    Ok(())
+}
+
+#[mocktopus::test]
+#[should_panic(expected = "failure in test")]
+fn when_test_has_attributes_then_they_are_kept() {
+    panic!("failure in test");
+}
+
+#[test]
+fn when_test_ends_then_thread_mocks_are_cleared() {
+    when_mocked_in_test_then_returns_mock_result();
+
+    assert!(!no_args.is_mocked());
+    assert_eq!(0, no_args());
+}
+
+#[test]
+fn when_test_panics_then_thread_mocks_are_cleared() {
+    let result = panic::catch_unwind(|| {
+        when_mocked_in_test_then_returns_mock_result();
+        no_args.mock_safe(|| MockResult::Return(1));
+        when_test_has_attributes_then_they_are_kept();
+    });
+
+    assert!(result.is_err());
+    assert!(!no_args.is_mocked());
+}
+
+#[test]
+fn when_mocks_left_by_previous_test_then_fails_and_clears_them() {
+    no_args.mock_safe(|| MockResult::Return(1));
+
+    let payload = panic::catch_unwind(when_test_run_then_runs_inside_fresh_context).unwrap_err();
+
+    let message = payload.downcast_ref::<String>().unwrap();
+    assert!(message.starts_with("Mocks left by a previous test were cleared:\nthread mocks:\n"));
+    assert!(message.contains("test_attribute::no_args"));
+    assert!(!no_args.is_mocked());
+}
+
+#[mocktopus::tokio_test]
+async fn when_mocked_in_async_test_then_returns_mock_result() {
+    no_args.mock_safe(|| MockResult::Return(1));
+
+    tokio::task::yield_now().await;
+    assert_eq!(1, no_args());
+}
+
+#[mocktopus::tokio_test(flavor = "current_thread")]
+async fn when_async_test_has_arguments_then_they_are_passed_to_tokio() {
+    assert_eq!(2, active_mocks().layers().len());
+}
+
+#[test]
+fn when_async_test_ends_then_thread_mocks_are_cleared() {
+    when_mocked_in_async_test_then_returns_mock_result();
+
+    assert!(!no_args.is_mocked());
+}
+
+#[mocktopus::test(fixture(defaults))]
+fn when_test_uses_fixture_then_its_mocks_are_set_up() {
+    assert_eq!(10, no_args());
+    assert_eq!(1, other_no_args());
+    assert_eq!(2, other_no_args());
+}
+
+#[mocktopus::test(fixture(defaults, fixtures::overrides))]
+fn when_test_uses_many_fixtures_then_later_ones_replace_mocks() {
+    assert_eq!(20, no_args());
+    assert_eq!(1, other_no_args());
+}
+
+#[mocktopus::test(fixture(defaults))]
+fn when_test_with_fixture_mocks_then_replaces_fixture_mocks() {
+    no_args.mock_safe(|| MockResult::Return(30));
+
+    assert_eq!(30, no_args());
+}
+
+#[mocktopus::test(fixture(defaults))]
+fn when_test_with_fixture_mocks_in_context_then_replaces_fixture_mocks() {
+    MockContext::new()
+        .mock_safe(no_args, || MockResult::Return(30))
+        .run(|| assert_eq!(30, no_args()));
+}
+
+#[test]
+fn when_test_with_fixture_run_again_then_gets_fresh_mocks() {
+    when_test_uses_fixture_then_its_mocks_are_set_up();
+    when_test_uses_fixture_then_its_mocks_are_set_up();
+
+    assert!(!no_args.is_mocked());
+}
+
+#[mocktopus::tokio_test(fixture(defaults), flavor = "current_thread")]
+async fn when_async_test_uses_fixture_then_its_mocks_are_set_up() {
+    tokio::task::yield_now().await;
+
+    assert_eq!(10, no_args());
+}